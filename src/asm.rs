@@ -0,0 +1,201 @@
+// Text form of a program: one instruction per line in the form `SvmInstruction` displays as, each
+// optionally prefixed by its gene order the way `evolve` prints them:
+//
+// # comment
+// 0.01439: (Jis 25 16)
+// 0.01632: (Jis 2 19)
+//
+// If no line has an order, the genes are spread evenly over 0..1 in the order they're written.
+//
+// The text doesn't say what machine the program runs on; asm takes that from its options, which
+// disasm prints in a comment.
+
+use super::cli::AsmOpts;
+use super::genetics::Gene;
+use super::svm::{InstructionSet, SvmConfig, SvmInstruction};
+
+pub fn parse_program(text: &str) -> Result<Vec<Gene>, String> {
+    let mut lines = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() { continue; }
+        let (order, ins) = match line.find(':') {
+            Some(colon) => {
+                let order = line[..colon].trim().parse::<f64>()
+                    .map_err(|e| format!("line {}: bad order: {}", i+1, e))?;
+                (Some(order), &line[colon+1..])
+            },
+            None => (None, line),
+        };
+        let ins:SvmInstruction = ins.parse().map_err(|e| format!("line {}: {}", i+1, e))?;
+        lines.push((i+1, order, ins));
+    }
+
+    let num_ordered = lines.iter().filter(|(_, order, _)| order.is_some()).count();
+    if num_ordered == 0 {
+        let len = lines.len() as f64;
+        return Ok(lines.into_iter().enumerate().map(|(i, (_, _, ins))| Gene{
+            order: (i as f64 + 0.5) / len,
+            ins,
+        }).collect());
+    }
+    if num_ordered != lines.len() {
+        return Err(String::from("either every instruction or none of them must have an order"));
+    }
+    let mut genes:Vec<Gene> = Vec::with_capacity(lines.len());
+    let mut prev_written = 0.0;
+    for (line, order, ins) in lines {
+        let written = order.unwrap();
+        if !(0.0..1.0).contains(&written) {
+            return Err(format!("line {}: order must be in 0..1", line));
+        }
+        if written < prev_written {
+            return Err(format!("line {}: orders must be increasing", line));
+        }
+        // evolve prints orders rounded, so several lines can show the same one. Nudge repeats up
+        // to the next float so the instructions stay in the order they were written.
+        let order = match genes.last() {
+            Some(prev) if written <= prev.order => f64::from_bits(prev.order.to_bits() + 1),
+            _ => written,
+        };
+        prev_written = written;
+        genes.push(Gene{order, ins});
+    }
+    Ok(genes)
+}
+
+/// Writes genes in the form `parse_program` reads, keeping the orders exact.
+pub fn format_program(genes: &[Gene]) -> String {
+    let mut res = String::new();
+    for gene in genes {
+        res.push_str(&format!("{}: {}\n", gene.order, gene.ins));
+    }
+    res
+}
+
+/// The machine to assemble `genes` for: the original layout with whatever `opts` changes, and the
+/// extensions the program uses. Without --registers there are as many as the layout and the
+/// program need, at least the original 32.
+pub fn machine(genes: &[Gene], num_inputs: usize, opts: &AsmOpts) -> Result<SvmConfig, String> {
+    let mut svm = SvmConfig::original(num_inputs);
+    svm.output = opts.output_register.unwrap_or(svm.output);
+    svm.first_input = opts.first_input.unwrap_or(svm.first_input);
+    svm.instruction_set = InstructionSet::covering(genes.iter().map(|g| g.ins));
+    if let Some(step_budget) = opts.step_budget {
+        svm.instruction_set.step_budget = step_budget;
+    }
+    svm.num_registers = match opts.registers {
+        Some(registers) => registers,
+        None => {
+            let max_register = genes.iter().map(|g| usize::from(g.ins.max_register())).max().unwrap_or_default();
+            svm.num_registers.max(max_register + 1).max(svm.inputs().end).max(usize::from(svm.output) + 1)
+        }
+    };
+    svm.validate()?;
+    svm.check_program(genes.iter().map(|g| g.ins))?;
+    Ok(svm)
+}
+
+/// The asm options that give back `svm`, for disasm to print
+pub fn machine_flags(svm: &SvmConfig) -> String {
+    format!(
+        "--registers {} --output-register {} --first-input {} --step-budget {}",
+        svm.num_registers, svm.output, svm.first_input, svm.instruction_set.step_budget,
+    )
+}
+
+#[cfg(test)]
+mod asm_test {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::svm::SvmInstructionTy;
+
+    #[test]
+    fn round_trip() {
+        let genes = vec![
+            Gene{order: 0.014391823, ins: SvmInstruction{ty: SvmInstructionTy::Jis, dest: 25, src: 16}},
+            Gene{order: 0.5, ins: SvmInstruction{ty: SvmInstructionTy::Mov, dest: 0, src: 31}},
+        ];
+        assert_eq!(parse_program(&format_program(&genes)).unwrap(), genes);
+    }
+
+    fn opts(flags: &str) -> AsmOpts {
+        let mut opts = AsmOpts{
+            input: PathBuf::new(),
+            output: PathBuf::new(),
+            features: String::new(),
+            registers: None,
+            output_register: None,
+            first_input: None,
+            step_budget: None,
+        };
+        let flags:Vec<&str> = flags.split_whitespace().collect();
+        for flag in flags.chunks(2) {
+            match flag[0] {
+                "--registers" => opts.registers = Some(flag[1].parse().unwrap()),
+                "--output-register" => opts.output_register = Some(flag[1].parse().unwrap()),
+                "--first-input" => opts.first_input = Some(flag[1].parse().unwrap()),
+                "--step-budget" => opts.step_budget = Some(flag[1].parse().unwrap()),
+                _ => panic!("{}", flag[0]),
+            }
+        }
+        opts
+    }
+
+    #[test]
+    fn machine_round_trip() {
+        let genes = parse_program("xor 11 2
+pop 3 7
+seb 11 4").unwrap();
+        let mut svm = SvmConfig::original(6);
+        svm.num_registers = 12;
+        svm.output = 11;
+        svm.first_input = 2;
+        svm.instruction_set = InstructionSet::covering(genes.iter().map(|g| g.ins));
+        svm.instruction_set.step_budget = 77;
+        let text = format_program(&genes);
+        let read = parse_program(&text).unwrap();
+        assert_eq!(read, genes);
+        assert_eq!(machine(&read, 6, &opts(&machine_flags(&svm))), Ok(svm));
+
+        // without options it's the original layout, grown to fit
+        let original = machine(&read, 6, &opts("")).unwrap();
+        assert_eq!((original.num_registers, original.output, original.first_input), (32, 0, 1));
+        let far = parse_program("xor 40 1").unwrap();
+        assert_eq!(machine(&far, 6, &opts("")).unwrap().num_registers, 41);
+
+        assert!(machine(&read, 6, &opts("--registers 11 --output-register 10 --first-input 2")).is_err());
+        assert!(machine(&read, 6, &opts("--registers 12 --output-register 3 --first-input 2")).is_err());
+        assert!(machine(&read, 6, &opts("--registers 12 --first-input 7")).is_err());
+    }
+
+    #[test]
+    fn without_orders() {
+        let genes = parse_program("# comment\nxor 1 2\n\n(Shl 0 3) # trailing\n").unwrap();
+        assert_eq!(genes.iter().map(|g| g.order).collect::<Vec<_>>(), vec![0.25, 0.75]);
+        assert_eq!(genes[0].ins, SvmInstruction{ty: SvmInstructionTy::Xor, dest: 1, src: 2});
+        assert_eq!(genes[1].ins, SvmInstruction{ty: SvmInstructionTy::Shl, dest: 0, src: 3});
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_program("xor 1").is_err());
+        assert!(parse_program("shl 1 32").is_err());
+        assert!(parse_program("xor 1 256").is_err());
+        assert!(parse_program("nop 1 2").is_err());
+        assert!(parse_program("0.5: xor 1 2\nxor 1 2").is_err());
+        assert!(parse_program("0.5: xor 1 2\n0.25: xor 1 2").is_err());
+    }
+
+    #[test]
+    fn repeated_orders() {
+        let genes = parse_program("0.5: xor 1 2\n0.5: add 1 2\n0.5: sub 1 2\n0.75: and 1 2").unwrap();
+        let tys:Vec<_> = genes.iter().map(|g| g.ins.ty).collect();
+        assert_eq!(tys, vec![SvmInstructionTy::Xor, SvmInstructionTy::Add, SvmInstructionTy::Sub, SvmInstructionTy::And]);
+        let mut sorted = genes.clone();
+        sorted.sort();
+        assert_eq!(sorted, genes);
+        assert_eq!(genes[0].order, 0.5);
+        assert!(genes[1].order > 0.5 && genes[2].order > genes[1].order && genes[2].order < 0.50001);
+    }
+}
@@ -0,0 +1,294 @@
+// Simple predictors built straight from the word map, to see how close evolved programs get to
+// them. Both pick each letter of a guess on its own: guessed if leaving it out would cost more in
+// false negatives than putting it in costs in false positives, with every partial word weighted by
+// how likely `random_partial_word` is to reach it. That's the best guess for the weighted error
+// sum, which is what `trial_score` squares.
+
+use std::fmt;
+
+use super::*;
+use exact::walk_probability;
+
+struct Example<const N: usize> {
+    word: OWord<N>,
+    weight: f64,
+    real: u32,
+}
+
+/// Every partial word in the map, sorted so sums come out the same on every run
+fn examples<const N: usize>(map: &WMap<N>) -> Vec<Example<N>> {
+    let mut examples:Vec<Example<N>> = map.iter().map(|(word, set)| Example{
+        word: *word,
+        weight: walk_probability(map, word, filled(word)),
+        real: (*set).into(),
+    }).collect();
+    examples.sort_unstable_by_key(|e| e.word);
+    examples
+}
+
+fn filled<const N: usize>(word: &OWord<N>) -> usize {
+    word.iter().take_while(|c| c.is_some()).count()
+}
+
+/// The letter before the first unfilled one, or 0 if there isn't one
+fn previous<const N: usize>(word: &OWord<N>) -> usize {
+    match filled(word) {
+        0 => 0,
+        n => u8::from(word[n-1].unwrap()).into(),
+    }
+}
+
+/// Weight of the partial words each letter is allowed in, and of all of them.
+#[derive(Debug,Clone,Copy)]
+struct Tally {
+    allowed: [f64; 27],
+    total: f64,
+}
+
+impl Default for Tally {
+    fn default() -> Self {
+        Tally{ allowed: [0.0; 27], total: 0.0 }
+    }
+}
+
+impl Tally {
+    fn add<const N: usize>(&mut self, example: &Example<N>) {
+        for l in 1..27 {
+            if example.real & (1 << l) != 0 {
+                self.allowed[l] += example.weight;
+            }
+        }
+        self.total += example.weight;
+    }
+
+    fn minus(&self, other: &Tally) -> Tally {
+        let mut res = *self;
+        for l in 1..27 {
+            res.allowed[l] -= other.allowed[l];
+        }
+        res.total -= other.total;
+        res
+    }
+
+    fn guess(&self, opts: &FitnessOpts) -> u32 {
+        (1..27)
+            .filter(|&l| self.allowed[l] * opts.weight_false_negative > (self.total - self.allowed[l]) * opts.weight_false_positive)
+            .fold(0, |guess, l| guess | (1 << l))
+    }
+
+    /// Weighted errors of `guess`
+    fn cost(&self, opts: &FitnessOpts) -> f64 {
+        (1..27)
+            .map(|l| f64::min(self.allowed[l] * opts.weight_false_negative, (self.total - self.allowed[l]) * opts.weight_false_positive))
+            .sum()
+    }
+}
+
+/// A guess for each number of filled letters and letter before the first unfilled one.
+pub struct LetterTable {
+    guesses: Vec<[u32; 27]>,
+}
+
+impl LetterTable {
+    pub fn build<const N: usize>(map: &WMap<N>, opts: &FitnessOpts) -> Self {
+        let mut tallies = vec![[Tally::default(); 27]; N];
+        for example in examples(map) {
+            tallies[filled(&example.word)][previous(&example.word)].add(&example);
+        }
+        LetterTable{
+            guesses: tallies.iter().map(|row| {
+                let mut guesses = [0u32; 27];
+                for (guess, tally) in guesses.iter_mut().zip(row) {
+                    *guess = tally.guess(opts);
+                }
+                guesses
+            }).collect(),
+        }
+    }
+
+    pub fn predict<const N: usize>(&self, word: &OWord<N>) -> u32 {
+        self.guesses[filled(word)][previous(word)]
+    }
+
+    /// Entries that guess anything
+    pub fn entries(&self) -> usize {
+        self.guesses.iter().flatten().filter(|g| **g != 0).count()
+    }
+}
+
+impl fmt::Display for LetterTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (depth, row) in self.guesses.iter().enumerate() {
+            for (prev, guess) in row.iter().enumerate().filter(|(_, g)| **g != 0) {
+                let prev = if prev == 0 { '^' } else { FChar::new(prev as u8).into() };
+                writeln!(f, "{:>2} {} {}", depth, prev, letters(*guess))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+enum Node {
+    Leaf(u32),
+    /// Goes to `yes` if the letter at `position` is `letter`, where `None` is unfilled
+    Split {
+        position: usize,
+        letter: Option<FChar>,
+        yes: Box<Node>,
+        no: Box<Node>,
+    },
+}
+
+/// Binary tree of "is this position this letter" questions, grown greedily to a fixed depth.
+pub struct DecisionTree {
+    root: Node,
+}
+
+impl DecisionTree {
+    pub fn build<const N: usize>(map: &WMap<N>, depth: usize, opts: &FitnessOpts) -> Self {
+        let examples = examples(map);
+        DecisionTree{ root: grow(examples.iter().collect(), depth, opts) }
+    }
+
+    pub fn predict<const N: usize>(&self, word: &OWord<N>) -> u32 {
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Leaf(guess) => return *guess,
+                Node::Split{position, letter, yes, no} => {
+                    node = if word[*position] == *letter { yes } else { no };
+                }
+            }
+        }
+    }
+
+    pub fn leaves(&self) -> usize {
+        fn count(node: &Node) -> usize {
+            match node {
+                Node::Leaf(_) => 1,
+                Node::Split{yes, no, ..} => count(yes) + count(no),
+            }
+        }
+        count(&self.root)
+    }
+}
+
+fn slot(letter: Option<FChar>) -> usize {
+    letter.map_or(0, |l| u8::from(l).into())
+}
+
+fn grow<const N: usize>(examples: Vec<&Example<N>>, depth: usize, opts: &FitnessOpts) -> Node {
+    let mut tally = Tally::default();
+    for example in &examples {
+        tally.add(example);
+    }
+    if depth == 0 {
+        return Node::Leaf(tally.guess(opts));
+    }
+
+    // tally for "position i is letter l" for every i and l at once
+    let mut buckets = vec![[Tally::default(); 27]; N];
+    for example in &examples {
+        for (bucket, letter) in buckets.iter_mut().zip(example.word) {
+            bucket[slot(letter)].add(example);
+        }
+    }
+    let mut best = None;
+    let mut best_cost = tally.cost(opts) * (1.0 - 1e-9);
+    for (position, bucket) in buckets.iter().enumerate() {
+        for (l, yes) in bucket.iter().enumerate() {
+            let no = tally.minus(yes);
+            // rounding can leave a little weight behind when every partial word is on one side
+            if yes.total == 0.0 || no.total <= tally.total * 1e-12 {
+                continue;
+            }
+            let cost = yes.cost(opts) + no.cost(opts);
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some((position, l));
+            }
+        }
+    }
+
+    match best {
+        None => Node::Leaf(tally.guess(opts)),
+        Some((position, l)) => {
+            let letter = if l == 0 { None } else { Some(FChar::new(l as u8)) };
+            let (yes, no) = examples.into_iter().partition(|e| e.word[position] == letter);
+            Node::Split{
+                position,
+                letter,
+                yes: Box::new(grow(yes, depth - 1, opts)),
+                no: Box::new(grow(no, depth - 1, opts)),
+            }
+        }
+    }
+}
+
+impl fmt::Display for DecisionTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn show(f: &mut fmt::Formatter<'_>, node: &Node, indent: usize) -> fmt::Result {
+            match node {
+                Node::Leaf(guess) => writeln!(f, "{:indent$}{:?}", "", letters(*guess), indent = indent),
+                Node::Split{position, letter, yes, no} => {
+                    let letter = letter.map_or('.', char::from);
+                    writeln!(f, "{:indent$}{} is {}:", "", position, letter, indent = indent)?;
+                    show(f, yes, indent + 2)?;
+                    writeln!(f, "{:indent$}else:", "", indent = indent)?;
+                    show(f, no, indent + 2)
+                }
+            }
+        }
+        show(f, &self.root, 0)
+    }
+}
+
+#[cfg(test)]
+mod baseline_test {
+    use super::*;
+
+    fn map() -> WMap<4> {
+        let words:Vec<Word<4>> = ["abed", "aces", "bead", "beds", "odes", "ogre"].iter()
+            .map(|w| parse_oword::<4>(w).unwrap().map(Option::unwrap))
+            .collect();
+        build_map(&words)
+    }
+
+    fn opts() -> FitnessOpts {
+        FitnessOpts{ weight_false_positive: 1000.0, weight_false_negative: 4000.0, weight_instruction: 0.1, trials: 0 }
+    }
+
+    fn set(s: &str) -> u32 {
+        u32::from(s.chars().fold(CharSet::default(), |set, c| set.set(FChar::try_from(c).unwrap())))
+    }
+
+    #[test]
+    fn letter_table() {
+        let table = LetterTable::build(&map(), &opts());
+        assert_eq!(table.predict(&parse_oword::<4>("").unwrap()), set("abo"));
+        assert_eq!(table.predict(&parse_oword::<4>("b").unwrap()), set("e"));
+        assert_eq!(table.predict(&parse_oword::<4>("be").unwrap()), set("ad"));
+        // "abe", "ace" and "ode" share an entry, and missing d costs more than guessing it
+        assert_eq!(table.predict(&parse_oword::<4>("ace").unwrap()), set("ds"));
+    }
+
+    #[test]
+    fn decision_tree() {
+        let map = map();
+        assert_eq!(DecisionTree::build(&map, 0, &opts()).leaves(), 1);
+
+        // the weighted errors each split is picked to lower
+        let cost = |tree: &DecisionTree| examples(&map).iter().map(|e| {
+            let (false_positives, false_negatives) = guess_errors(tree.predict(&e.word), e.real);
+            e.weight * (f64::from(false_positives) * 1000.0 + f64::from(false_negatives) * 4000.0)
+        }).sum::<f64>();
+        let mut previous_cost = f64::INFINITY;
+        for depth in 0..8 {
+            let tree = DecisionTree::build(&map, depth, &opts());
+            assert!(tree.leaves() <= 1 << depth);
+            assert!(cost(&tree) <= previous_cost + 1e-9);
+            previous_cost = cost(&tree);
+        }
+        assert!(previous_cost < 1e-9, "{}", previous_cost);
+    }
+}
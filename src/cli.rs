@@ -0,0 +1,384 @@
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use super::crossover::CrossoverTy;
+use super::islands::Topology;
+use super::niching::{DistanceTy, NichingTy};
+use super::search::Filter;
+use super::selection::SelectionTy;
+
+#[derive(Debug,StructOpt)]
+#[structopt(name = "fws2", about = "Evolves SVM programs that predict the next letters of a word square")]
+pub struct Opt {
+    /// Word list, one word per line
+    #[structopt(long, short, global = true, parse(from_os_str), default_value = "/home/shelvacu/words/uncompressible.txt")]
+    pub words: PathBuf,
+
+    /// Length of the words in the square; only words of exactly this length are read
+    #[structopt(long, global = true, default_value = "10")]
+    pub size: usize,
+
+    /// Seed for all randomness; a random one is picked and printed if not given
+    #[structopt(long, global = true)]
+    pub seed: Option<u64>,
+
+    #[structopt(subcommand)]
+    pub cmd: Command,
+}
+
+#[derive(Debug,StructOpt)]
+pub enum Command {
+    /// Run the genetic algorithm, writing a checkpoint every so often
+    Evolve(EvolveOpts),
+    /// Search for word squares using only the word map
+    Search(SearchOpts),
+    /// Score a genome from a checkpoint
+    Eval(EvalOpts),
+    /// Turn a program written as text into a checkpoint holding just that genome
+    Asm(AsmOpts),
+    /// Print a genome from a checkpoint as text that asm can read back
+    Disasm(DisasmOpts),
+    /// Save a single genome from a checkpoint as a binary program file
+    Export(ExportOpts),
+    /// Remove instructions from a genome that don't affect its output, and print the result
+    Optimize(OptimizeOpts),
+    /// Run a genome on one partial word and print every instruction it runs
+    Trace(TraceOpts),
+    /// Score simple predictors built straight from the word map, to compare evolved programs to
+    Baseline(BaselineOpts),
+    /// List the genomes of a checkpoint that no other beats on false positives, false negatives
+    /// and length all at once, to pick a trade-off from
+    Front(FrontOpts),
+}
+
+#[derive(Debug,Clone,Copy,StructOpt)]
+pub struct FitnessOpts {
+    /// Penalty for each letter guessed as allowed that isn't
+    #[structopt(long, default_value = "1000.0")]
+    pub weight_false_positive: f64,
+
+    /// Penalty for each allowed letter that wasn't guessed
+    #[structopt(long, default_value = "4000.0")]
+    pub weight_false_negative: f64,
+
+    /// Penalty for each instruction in the program
+    #[structopt(long, default_value = "0.1")]
+    pub weight_instruction: f64,
+
+    /// Number of random partial words each genome is scored on
+    #[structopt(long, default_value = "1000", parse(try_from_str = parse_at_least_one))]
+    pub trials: usize,
+}
+
+/// For counts that can't be 0: scores are averaged over the trials, and the pool is split into
+/// islands of equal length
+fn parse_at_least_one(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err(String::from("must be at least 1")),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_radius(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(radius) if radius > 0.0 && radius.is_finite() => Ok(radius),
+        Ok(_) => Err(String::from("must be more than 0")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Debug,StructOpt)]
+pub struct EvolveOpts {
+    /// Continue from a roundN.fws (or older roundN.json.gz) checkpoint instead of a random pool
+    #[structopt(long, parse(from_os_str))]
+    pub resume: Option<PathBuf>,
+
+    /// Number of genomes in the pool, or in each island
+    #[structopt(long, default_value = "1000", parse(try_from_str = parse_at_least_one))]
+    pub pool_size: usize,
+
+    /// Maximum number of instructions a genome can grow to
+    #[structopt(long, default_value = "1024")]
+    pub instructions_max: usize,
+
+    /// Number of instructions in each genome of a fresh pool
+    #[structopt(long, default_value = "32")]
+    pub instructions_init: usize,
+
+    /// Write a checkpoint every this many rounds; 0 never does
+    #[structopt(long, default_value = "16")]
+    pub checkpoint_interval: usize,
+
+    /// Number of threads to score genomes on; 0 uses one per core
+    #[structopt(long, default_value = "0")]
+    pub threads: usize,
+
+    /// Score every genome in a round on the same batch of partial words instead of each drawing
+    /// its own, so differences in fitness aren't down to which words were drawn
+    #[structopt(long)]
+    pub shared_trials: bool,
+
+    /// How genomes survive to be the parents of the next round: linear-rank, tournament,
+    /// truncation or proportional
+    #[structopt(long, default_value = "linear-rank")]
+    pub selection: SelectionTy,
+
+    /// Genomes in each tournament, for --selection tournament
+    #[structopt(long, default_value = "3")]
+    pub tournament_size: usize,
+
+    /// Fraction of the pool that survives each round, for every selection but linear-rank, which
+    /// keeps about half
+    #[structopt(long, default_value = "0.5")]
+    pub survivors: f64,
+
+    /// The best this many genomes always survive, whatever the selection
+    #[structopt(long, default_value = "0")]
+    pub elitism: usize,
+
+    /// How a child's genes are made from its parents': diff, one-point, two-point, uniform or
+    /// homologous
+    #[structopt(long, default_value = "diff")]
+    pub crossover: CrossoverTy,
+
+    /// Also change genes in place: their opcode, dest, src or order, duplicating a run of them or
+    /// swapping two. Each has its own rate, which adapts like the mutation rate.
+    #[structopt(long)]
+    pub point_mutations: bool,
+
+    /// Split the pool into this many islands that each select and breed on their own, trading
+    /// their best genomes every so often. Only for a fresh run. [default: 1]
+    #[structopt(long, parse(try_from_str = parse_at_least_one))]
+    pub islands: Option<usize>,
+
+    /// Which island each island's best genomes go to: ring or random
+    #[structopt(long, default_value = "ring")]
+    pub migration: Topology,
+
+    /// Migrate every this many rounds; 0 never does
+    #[structopt(long, default_value = "10")]
+    pub migration_interval: usize,
+
+    /// Number of genomes each island sends, which replace the worst of the island they go to
+    #[structopt(long, default_value = "5")]
+    pub migration_size: usize,
+
+    /// Keep the pool from filling with copies of the best: off, sharing (fitness is made worse
+    /// the more genomes are within --niche-radius) or crowding (children replace genomes like
+    /// them instead of the ones that didn't survive)
+    #[structopt(long, default_value = "off")]
+    pub niching: NichingTy,
+
+    /// How alike two genomes are, for niching and the diversity printed each round: genotypic
+    /// (share of genes only one has) or behavioral (share of guesses they disagree on)
+    #[structopt(long, default_value = "genotypic")]
+    pub distance: DistanceTy,
+
+    /// Distance under which genomes share fitness, for --niching sharing
+    #[structopt(long, default_value = "0.3", parse(try_from_str = parse_radius))]
+    pub niche_radius: f64,
+
+    /// Genomes each child is compared to before replacing the closest, for --niching crowding
+    #[structopt(long, default_value = "3")]
+    pub crowding_factor: usize,
+
+    /// Number of partial words, drawn once at the start of the run, that behavioral distance
+    /// compares outputs on
+    #[structopt(long, default_value = "64")]
+    pub behavior_trials: usize,
+
+    /// Rank genomes NSGA-II style instead of by fitness: by how many Pareto fronts of false
+    /// positives, false negatives and instruction count beat them, then by how spread out they are.
+    /// --selection truncation keeps the best fronts whole, as NSGA-II does. --selection proportional
+    /// and --niching sharing go by fitness instead, so neither can be used with it.
+    #[structopt(long)]
+    pub pareto: bool,
+
+    /// Rank genomes with any false negative on their trials below every genome with none, then by
+    /// false positives, then by length, for a filter that can prune the square search without
+    /// ever losing a square. Best with --shared-trials, so every genome faces the same words. Like
+    /// --pareto, it can't be used with --selection proportional or --niching sharing.
+    #[structopt(long, conflicts_with = "pareto")]
+    pub zero_false_negatives: bool,
+
+    /// Also score the best genome of each round on a batch of this many partial words, drawn once
+    /// at the start of the run
+    #[structopt(long, default_value = "0")]
+    pub validation_trials: usize,
+
+    /// Extra instructions random genes can use, comma separated (ldi,pop,lsb,rol,not,cmv,skn,lop)
+    /// or "all". A resumed run keeps the ones its checkpoint had and adds these.
+    #[structopt(long)]
+    pub extensions: Option<String>,
+
+    /// Steps a program can run before lop stops jumping back [default: 1024, or the checkpoint's]
+    #[structopt(long)]
+    pub step_budget: Option<u32>,
+
+    /// Registers the machine has. Only for a fresh run. [default: 32]
+    #[structopt(long)]
+    pub registers: Option<usize>,
+
+    /// Register the guess is read from. Only for a fresh run. [default: 0]
+    #[structopt(long)]
+    pub output_register: Option<u8>,
+
+    /// First of the registers the letters are put in. Only for a fresh run. [default: 1]
+    #[structopt(long)]
+    pub first_input: Option<u8>,
+
+    /// Extra input registers after the letters, comma separated (filled,used,vowels,successors) or
+    /// "all". Only for a fresh run.
+    #[structopt(long)]
+    pub features: Option<String>,
+
+    #[structopt(flatten)]
+    pub fitness: FitnessOpts,
+}
+
+#[derive(Debug,StructOpt)]
+pub struct SearchOpts {
+    /// Number of words (from the start of the word list) to try as the first row
+    #[structopt(long, default_value = "10")]
+    pub first_rows: usize,
+
+    /// Checkpoint to read a genome from, to narrow down each cell's letters with; the search is
+    /// run both with it and on the word map alone, and the two compared
+    #[structopt(long, parse(from_os_str))]
+    pub genome: Option<PathBuf>,
+
+    /// Position of the genome in the checkpoint's pool
+    #[structopt(long, default_value = "0")]
+    pub index: usize,
+
+    /// How the genome's guesses are used: intersect (only letters both it and the word map allow)
+    /// or substitute (its guesses alone, with finished squares checked against the word map)
+    #[structopt(long, default_value = "intersect")]
+    pub filter: Filter,
+}
+
+#[derive(Debug,StructOpt)]
+pub struct EvalOpts {
+    /// Checkpoint to read the genome from
+    #[structopt(parse(from_os_str))]
+    pub checkpoint: PathBuf,
+
+    /// Position of the genome in the checkpoint's pool; the pool is sorted best first, island by island
+    #[structopt(long, default_value = "0")]
+    pub index: usize,
+
+    #[structopt(flatten)]
+    pub fitness: FitnessOpts,
+}
+
+#[derive(Debug,StructOpt)]
+pub struct AsmOpts {
+    /// Program text, one instruction per line
+    #[structopt(parse(from_os_str))]
+    pub input: PathBuf,
+
+    /// Checkpoint to write; it can be given to eval, or to evolve --resume to evolve from it
+    #[structopt(parse(from_os_str))]
+    pub output: PathBuf,
+
+    /// Extra input registers the program expects after the letters, as for evolve
+    #[structopt(long, default_value = "")]
+    pub features: String,
+
+    /// Registers the machine has [default: 32, or as many as the program uses]
+    #[structopt(long)]
+    pub registers: Option<usize>,
+
+    /// Register the guess is read from [default: 0]
+    #[structopt(long)]
+    pub output_register: Option<u8>,
+
+    /// First of the registers the letters are put in [default: 1]
+    #[structopt(long)]
+    pub first_input: Option<u8>,
+
+    /// Steps a program can run before lop stops jumping back [default: 1024]
+    #[structopt(long)]
+    pub step_budget: Option<u32>,
+}
+
+#[derive(Debug,StructOpt)]
+pub struct DisasmOpts {
+    /// Checkpoint to read the genome from
+    #[structopt(parse(from_os_str))]
+    pub checkpoint: PathBuf,
+
+    /// Position of the genome in the checkpoint's pool; the pool is sorted best first, island by island
+    #[structopt(long, default_value = "0")]
+    pub index: usize,
+}
+
+#[derive(Debug,StructOpt)]
+pub struct ExportOpts {
+    /// Checkpoint to read the genome from
+    #[structopt(parse(from_os_str))]
+    pub checkpoint: PathBuf,
+
+    /// Program file to write; anything that reads a checkpoint also reads these
+    #[structopt(parse(from_os_str))]
+    pub output: PathBuf,
+
+    /// Position of the genome in the checkpoint's pool; the pool is sorted best first, island by island
+    #[structopt(long, default_value = "0")]
+    pub index: usize,
+}
+
+#[derive(Debug,StructOpt)]
+pub struct OptimizeOpts {
+    /// Checkpoint to read the genome from
+    #[structopt(parse(from_os_str))]
+    pub checkpoint: PathBuf,
+
+    /// Position of the genome in the checkpoint's pool; the pool is sorted best first, island by island
+    #[structopt(long, default_value = "0")]
+    pub index: usize,
+
+    /// Also save the optimized genome as a binary program file
+    #[structopt(long, short, parse(from_os_str))]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug,StructOpt)]
+pub struct TraceOpts {
+    /// Checkpoint to read the genome from
+    #[structopt(parse(from_os_str))]
+    pub checkpoint: PathBuf,
+
+    /// Letters filled in so far, with . or _ for letters that aren't, e.g. "ab" or "a.c"
+    pub word: String,
+
+    /// Position of the genome in the checkpoint's pool; the pool is sorted best first, island by island
+    #[structopt(long, default_value = "0")]
+    pub index: usize,
+
+    /// Stop after this many steps
+    #[structopt(long, default_value = "100000")]
+    pub max_steps: u32,
+}
+
+#[derive(Debug,StructOpt)]
+pub struct BaselineOpts {
+    /// Depth of the decision tree
+    #[structopt(long, default_value = "8")]
+    pub depth: usize,
+
+    /// Also print the table and the tree
+    #[structopt(long)]
+    pub show: bool,
+
+    #[structopt(flatten)]
+    pub fitness: FitnessOpts,
+}
+
+#[derive(Debug,StructOpt)]
+pub struct FrontOpts {
+    /// Checkpoint to read the front from
+    #[structopt(parse(from_os_str))]
+    pub checkpoint: PathBuf,
+}
@@ -0,0 +1,345 @@
+// Programs get run thousands of times per genome per round, so instead of having `SvmState`
+// dispatch on each `SvmInstruction` every time, they're decoded once into `Op`s: bit numbers
+// become masks, shift amounts become plain numbers, and jumps know the index they land on.
+//
+// `run_batch` goes further and runs one program over `BATCH_LANES` inputs at once. Each register
+// holds one value per lane, so every op is a loop over lanes the compiler can vectorize. Lanes
+// can't take different paths, so a forward jump instead marks the lanes where it's taken, and the
+// instructions it skips leave those lanes alone. Backward jumps can't be handled that way, so
+// programs with Lop run each lane separately.
+
+use super::svm::*;
+
+pub const BATCH_LANES:usize = 64;
+/// A register's value in every lane; `run_batch` takes a slice of these, one per register
+pub type Lanes = [u32; BATCH_LANES];
+
+/// Sets `dest` to `f(dest, src)` in every lane, or only in lanes where `skip` is 0 if there is one.
+#[inline(always)]
+fn lanewise(regs: &mut [Lanes], dest: u8, src: u8, skip: Option<&Lanes>, f: impl Fn(u32, u32) -> u32) {
+    let src = regs[src as usize];
+    let dest = &mut regs[dest as usize];
+    match skip {
+        None => for l in 0..BATCH_LANES {
+            dest[l] = f(dest[l], src[l]);
+        },
+        Some(skip) => for l in 0..BATCH_LANES {
+            dest[l] = (f(dest[l], src[l]) & !skip[l]) | (dest[l] & skip[l]);
+        },
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum Op {
+    Xor(u8, u8),
+    Add(u8, u8),
+    Sub(u8, u8),
+    And(u8, u8),
+    Oor(u8, u8),
+    Mov(u8, u8),
+    /// Set a register to a constant, from Ldi, `Xor r r` and `Sub r r`
+    Set(u8, u32),
+    Shl(u8, u32),
+    Shr(u8, u32),
+    Rol(u8, u32),
+    /// Seb, or'ing in a mask
+    OorImm(u8, u32),
+    /// Clb, and'ing with a mask
+    AndImm(u8, u32),
+    Pop(u8, u8),
+    Lsb(u8, u8),
+    Not(u8, u8),
+    Cmv(u8, u8),
+    /// Jis/Jns/Skn: continue at `to` if the masked register is (or isn't) zero
+    JumpIfZero{ reg: u8, mask: u32, to: usize },
+    JumpIfNonZero{ reg: u8, mask: u32, to: usize },
+    /// Lop: go back to `to` if the register is nonzero and there are steps left in the budget
+    LoopIfNonZero{ reg: u8, to: usize },
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct CompiledProgram {
+    ops: Vec<Op>,
+    config: SvmConfig,
+    has_loops: bool,
+}
+
+impl CompiledProgram {
+    /// Panics if the program uses registers `config` doesn't have.
+    pub fn compile(instructions: impl IntoIterator<Item=SvmInstruction>, config: &SvmConfig) -> Self {
+        use SvmInstructionTy::*;
+        let instructions:Vec<SvmInstruction> = instructions.into_iter().collect();
+        config.check_program(instructions.iter().copied()).unwrap();
+        let len = instructions.len();
+        let ops:Vec<Op> = instructions.iter().enumerate().map(|(i, ins)| {
+            let (d, s) = (ins.dest, ins.src);
+            // where a control instruction goes if it jumps; jumping past the end just finishes
+            let to = match ins.ty {
+                Jis | Jns => i + 2,
+                Skn => i + 1 + usize::from(s),
+                Lop => i.saturating_sub(usize::from(s) + 1),
+                _ => 0,
+            }.min(len);
+            match ins.ty {
+                Xor | Sub if d == s => Op::Set(d, 0),
+                Xor => Op::Xor(d, s),
+                Add => Op::Add(d, s),
+                Sub => Op::Sub(d, s),
+                And => Op::And(d, s),
+                Oor => Op::Oor(d, s),
+                Mov => Op::Mov(d, s),
+                Shl => Op::Shl(d, s.into()),
+                Shr => Op::Shr(d, s.into()),
+                Seb => Op::OorImm(d, 1 << s),
+                Clb => Op::AndImm(d, !(1 << s)),
+                Jis => Op::JumpIfNonZero{ reg: d, mask: 1 << s, to },
+                Jns => Op::JumpIfZero{ reg: d, mask: 1 << s, to },
+                Ldi => Op::Set(d, s.into()),
+                Pop => Op::Pop(d, s),
+                Lsb => Op::Lsb(d, s),
+                Rol => Op::Rol(d, s.into()),
+                Not => Op::Not(d, s),
+                Cmv => Op::Cmv(d, s),
+                Skn => Op::JumpIfZero{ reg: d, mask: u32::MAX, to },
+                Lop => Op::LoopIfNonZero{ reg: d, to },
+            }
+        }).collect();
+        let has_loops = ops.iter().any(|op| matches!(op, Op::LoopIfNonZero{..}));
+        CompiledProgram{ ops, config: *config, has_loops }
+    }
+
+    /// The machine the program was compiled for
+    pub fn config(&self) -> &SvmConfig {
+        &self.config
+    }
+
+    /// Number of instructions the program was compiled from
+    pub fn num_instructions(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Runs the program to the end, exactly as stepping an `SvmState` holding `regs` would.
+    /// `regs` has to have `config().num_registers` registers.
+    pub fn run(&self, regs: &mut [u32]) {
+        assert_eq!(regs.len(), self.config.num_registers);
+        let step_budget = self.config.instruction_set.step_budget;
+        let mut pc = 0;
+        let mut steps = 0;
+        while let Some(&op) = self.ops.get(pc) {
+            pc += 1;
+            steps += 1;
+            match op {
+                Op::Xor(d, s) => regs[d as usize] ^= regs[s as usize],
+                Op::Add(d, s) => regs[d as usize] = regs[d as usize].wrapping_add(regs[s as usize]),
+                Op::Sub(d, s) => regs[d as usize] = regs[d as usize].wrapping_sub(regs[s as usize]),
+                Op::And(d, s) => regs[d as usize] &= regs[s as usize],
+                Op::Oor(d, s) => regs[d as usize] |= regs[s as usize],
+                Op::Mov(d, s) => regs[d as usize] = regs[s as usize],
+                Op::Set(d, n) => regs[d as usize] = n,
+                Op::Shl(d, n) => regs[d as usize] <<= n,
+                Op::Shr(d, n) => regs[d as usize] >>= n,
+                Op::Rol(d, n) => regs[d as usize] = regs[d as usize].rotate_left(n),
+                Op::OorImm(d, mask) => regs[d as usize] |= mask,
+                Op::AndImm(d, mask) => regs[d as usize] &= mask,
+                Op::Pop(d, s) => regs[d as usize] = regs[s as usize].count_ones(),
+                Op::Lsb(d, s) => regs[d as usize] = regs[s as usize] & regs[s as usize].wrapping_neg(),
+                Op::Not(d, s) => regs[d as usize] = !regs[s as usize],
+                Op::Cmv(d, s) => if regs[s as usize] != 0 { regs[d as usize] = regs[s as usize] },
+                Op::JumpIfZero{ reg, mask, to } => if regs[reg as usize] & mask == 0 { pc = to },
+                Op::JumpIfNonZero{ reg, mask, to } => if regs[reg as usize] & mask != 0 { pc = to },
+                Op::LoopIfNonZero{ reg, to } => if regs[reg as usize] != 0 && steps < step_budget { pc = to },
+            }
+        }
+    }
+
+    /// Runs the program on every lane of `regs` at once; each lane ends up as if `run` had been
+    /// called on it.
+    pub fn run_batch(&self, regs: &mut [Lanes]) {
+        assert_eq!(regs.len(), self.config.num_registers);
+        if self.has_loops {
+            let mut lane = vec![0; regs.len()];
+            for l in 0..BATCH_LANES {
+                for (r, reg) in regs.iter().enumerate() {
+                    lane[r] = reg[l];
+                }
+                self.run(&mut lane);
+                for (r, reg) in regs.iter_mut().enumerate() {
+                    reg[l] = lane[r];
+                }
+            }
+            return;
+        }
+        // Index of the first instruction each lane runs again after a jump, and the furthest of
+        // those; while `i < skip_end` some lanes are skipping.
+        let mut skip_to = [0usize; BATCH_LANES];
+        let mut skip_end = 0;
+        // all ones in lanes skipping the current instruction
+        let mut skip = [0u32; BATCH_LANES];
+        for (i, &op) in self.ops.iter().enumerate() {
+            let masked = if i < skip_end {
+                for l in 0..BATCH_LANES {
+                    skip[l] = ((i < skip_to[l]) as u32).wrapping_neg();
+                }
+                Some(&skip)
+            } else {
+                None
+            };
+            match op {
+                Op::Xor(d, s) => lanewise(regs, d, s, masked, |a, b| a ^ b),
+                Op::Add(d, s) => lanewise(regs, d, s, masked, u32::wrapping_add),
+                Op::Sub(d, s) => lanewise(regs, d, s, masked, u32::wrapping_sub),
+                Op::And(d, s) => lanewise(regs, d, s, masked, |a, b| a & b),
+                Op::Oor(d, s) => lanewise(regs, d, s, masked, |a, b| a | b),
+                Op::Mov(d, s) => lanewise(regs, d, s, masked, |_, b| b),
+                Op::Set(d, n) => lanewise(regs, d, d, masked, |_, _| n),
+                Op::Shl(d, n) => lanewise(regs, d, d, masked, |a, _| a << n),
+                Op::Shr(d, n) => lanewise(regs, d, d, masked, |a, _| a >> n),
+                Op::Rol(d, n) => lanewise(regs, d, d, masked, |a, _| a.rotate_left(n)),
+                Op::OorImm(d, mask) => lanewise(regs, d, d, masked, |a, _| a | mask),
+                Op::AndImm(d, mask) => lanewise(regs, d, d, masked, |a, _| a & mask),
+                Op::Pop(d, s) => lanewise(regs, d, s, masked, |_, b| b.count_ones()),
+                Op::Lsb(d, s) => lanewise(regs, d, s, masked, |_, b| b & b.wrapping_neg()),
+                Op::Not(d, s) => lanewise(regs, d, s, masked, |_, b| !b),
+                Op::Cmv(d, s) => lanewise(regs, d, s, masked, |a, b| if b != 0 { b } else { a }),
+                Op::JumpIfZero{ reg, mask, to } | Op::JumpIfNonZero{ reg, mask, to } => {
+                    let want_zero = matches!(op, Op::JumpIfZero{..});
+                    let reg = &regs[reg as usize];
+                    let mut any = false;
+                    for l in 0..BATCH_LANES {
+                        // a jump that is itself skipped doesn't jump
+                        let taken = ((reg[l] & mask) == 0) == want_zero && i >= skip_to[l];
+                        skip_to[l] = if taken { to } else { skip_to[l] };
+                        any |= taken;
+                    }
+                    if any {
+                        skip_end = skip_end.max(to);
+                    }
+                },
+                Op::LoopIfNonZero{..} => unreachable!(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod compile_test {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn parse(text: &str) -> Vec<SvmInstruction> {
+        text.lines().map(|l| l.parse().unwrap()).collect()
+    }
+
+    fn all(num_registers: usize) -> SvmConfig {
+        let mut config = SvmConfig::original(4);
+        config.num_registers = num_registers;
+        config.instruction_set = InstructionSet::all();
+        config
+    }
+
+    fn random_value(rng: &mut StdRng) -> u32 {
+        // mostly sparse values like the letter sets the programs really see
+        if rng.gen() { 1 << rng.gen_range(0..32) } else { rng.gen() }
+    }
+
+    fn interpret(program: &[SvmInstruction], config: &SvmConfig, regs: &[u32]) -> Vec<u32> {
+        let mut state = SvmState::new(program, config);
+        state.memory_mut().copy_from_slice(regs);
+        while state.step() == StepResult::Continue {}
+        state.memory().to_vec()
+    }
+
+    #[test]
+    fn matches_interpreter() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut short_budget = all(32);
+        short_budget.instruction_set.step_budget = 150;
+        for config in &[SvmConfig::original(4), short_budget, all(8), all(100)] {
+            for _ in 0..500 {
+                let len = rng.gen_range(0..100);
+                let program:Vec<_> = (0..len).map(|_| SvmInstruction::random(&mut rng, config)).collect();
+                let compiled = CompiledProgram::compile(program.iter().copied(), config);
+                assert_eq!(compiled.num_instructions(), program.len());
+                for _ in 0..20 {
+                    let mut regs:Vec<u32> = (0..config.num_registers).map(|_| random_value(&mut rng)).collect();
+                    let expected = interpret(&program, config, &regs);
+                    compiled.run(&mut regs);
+                    assert_eq!(regs, expected, "{:?}", program);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn batch_matches_run() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for i in 0..600 {
+            let config = all([32, 8, 100][i % 3]);
+            let len = rng.gen_range(0..100);
+            // Lop makes run_batch fall back to running lanes one at a time, so leave it out of most
+            let program:Vec<_> = (0..len)
+                .map(|_| SvmInstruction::random(&mut rng, &config))
+                .filter(|ins| i % 10 == 0 || ins.ty != SvmInstructionTy::Lop)
+                .collect();
+            let compiled = CompiledProgram::compile(program, &config);
+            let mut batch = vec![[0; BATCH_LANES]; config.num_registers];
+            for reg in batch.iter_mut() {
+                for v in reg.iter_mut() {
+                    *v = random_value(&mut rng);
+                }
+            }
+            let mut expected = batch.clone();
+            for l in 0..BATCH_LANES {
+                let mut regs:Vec<u32> = expected.iter().map(|reg| reg[l]).collect();
+                compiled.run(&mut regs);
+                for (reg, v) in expected.iter_mut().zip(regs) {
+                    reg[l] = v;
+                }
+            }
+            compiled.run_batch(&mut batch);
+            assert_eq!(batch, expected, "{:?}", compiled);
+        }
+    }
+
+    #[test]
+    fn jumps_at_the_end() {
+        let mut regs = [0; 32];
+        CompiledProgram::compile(parse("seb 0 3\njis 0 3"), &SvmConfig::original(4)).run(&mut regs);
+        assert_eq!(regs[0], 8);
+        let mut regs = [0; 32];
+        CompiledProgram::compile(parse("seb 0 3\nskn 1 31\nseb 0 4"), &all(32)).run(&mut regs);
+        assert_eq!(regs[0], 8);
+    }
+
+    #[test]
+    fn skips_and_loops() {
+        let config = all(8);
+        let skip = CompiledProgram::compile(parse("skn 1 2\nseb 0 0\nseb 0 1\nseb 0 2"), &config);
+        let mut regs = [0; 8];
+        skip.run(&mut regs);
+        assert_eq!(regs[0], 0b100);
+        regs = [0; 8];
+        regs[1] = 1;
+        skip.run(&mut regs);
+        assert_eq!(regs[0], 0b111);
+
+        // doubles r0 three times, unless the budget runs out first
+        let count_down = parse("ldi 0 1\nldi 5 3\nldi 6 1\nshl 0 1\nsub 5 6\nlop 5 1");
+        let mut regs = [0; 8];
+        CompiledProgram::compile(count_down.iter().copied(), &config).run(&mut regs);
+        assert_eq!((regs[0], regs[5]), (8, 0));
+        let mut short = config;
+        short.instruction_set.step_budget = 6;
+        let mut regs = [0; 8];
+        CompiledProgram::compile(count_down.iter().copied(), &short).run(&mut regs);
+        assert_eq!((regs[0], regs[5]), (2, 2));
+        assert_eq!(interpret(&count_down, &short, &[0; 8]), regs);
+    }
+
+    #[test]
+    #[should_panic]
+    fn registers_out_of_range() {
+        CompiledProgram::compile(parse("mov 0 8"), &all(8));
+    }
+}
@@ -0,0 +1,277 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::str::FromStr;
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use super::cli::EvolveOpts;
+use super::genetics::{diff, DiffTy, Gene};
+use super::svm::SvmInstruction;
+
+/// Builds a child's genes from two parents'. Both parents and the child are sorted by order.
+pub trait Crossover: fmt::Debug + Send + Sync {
+    fn cross(&self, a: &[Gene], b: &[Gene], rng: &mut StdRng) -> Vec<Gene>;
+}
+
+/// Keeps the genes both parents share, and each block only one of them has with a coin flip.
+#[derive(Debug)]
+pub struct Diff;
+
+impl Crossover for Diff {
+    fn cross(&self, a: &[Gene], b: &[Gene], rng: &mut StdRng) -> Vec<Gene> {
+        let mut genes = Vec::new();
+        for mut block in diff(a.iter().copied(), b.iter().copied()) {
+            match block.ty {
+                DiffTy::Both => genes.append(&mut block.block),
+                _ => if rng.gen::<bool>() { genes.append(&mut block.block) },
+            }
+        }
+        genes
+    }
+}
+
+/// Orders spanned by either parent, to pick cut points from
+fn order_range(a: &[Gene], b: &[Gene]) -> Option<(f64, f64)> {
+    let first = a.first().into_iter().chain(b.first()).map(|g| g.order).reduce(f64::min)?;
+    let last = a.last().into_iter().chain(b.last()).map(|g| g.order).reduce(f64::max)?;
+    Some((first, last))
+}
+
+fn cut_point(range: (f64, f64), rng: &mut StdRng) -> f64 {
+    if range.0 < range.1 { rng.gen_range(range.0..range.1) } else { range.0 }
+}
+
+/// Genes of `a` ordered before a random cut, and of `b` from it on.
+#[derive(Debug)]
+pub struct OnePoint;
+
+impl Crossover for OnePoint {
+    fn cross(&self, a: &[Gene], b: &[Gene], rng: &mut StdRng) -> Vec<Gene> {
+        let cut = match order_range(a, b) {
+            Some(range) => cut_point(range, rng),
+            None => return Vec::new(),
+        };
+        a.iter().filter(|g| g.order < cut).chain(b.iter().filter(|g| g.order >= cut)).copied().collect()
+    }
+}
+
+/// Genes of `b` ordered between two random cuts, and of `a` outside them.
+#[derive(Debug)]
+pub struct TwoPoint;
+
+impl Crossover for TwoPoint {
+    fn cross(&self, a: &[Gene], b: &[Gene], rng: &mut StdRng) -> Vec<Gene> {
+        let range = match order_range(a, b) {
+            Some(range) => range,
+            None => return Vec::new(),
+        };
+        let (x, y) = (cut_point(range, rng), cut_point(range, rng));
+        let (lo, hi) = if x <= y { (x, y) } else { (y, x) };
+        let inside = |g: &&Gene| lo <= g.order && g.order < hi;
+        let mut genes:Vec<Gene> = a.iter().filter(|g| !inside(g)).chain(b.iter().filter(inside)).copied().collect();
+        genes.sort();
+        genes
+    }
+}
+
+/// Pairs the parents' genes up by position and takes each from either one with a coin flip. Past
+/// the end of the shorter parent the flip picks between the longer one's gene and nothing.
+#[derive(Debug)]
+pub struct Uniform;
+
+impl Crossover for Uniform {
+    fn cross(&self, a: &[Gene], b: &[Gene], rng: &mut StdRng) -> Vec<Gene> {
+        let mut genes:Vec<Gene> = (0..a.len().max(b.len()))
+            .filter_map(|i| if rng.gen::<bool>() { a.get(i) } else { b.get(i) })
+            .copied()
+            .collect();
+        genes.sort();
+        genes.dedup();
+        genes
+    }
+}
+
+/// How alike two instructions are, for lining genomes up
+fn similarity(a: SvmInstruction, b: SvmInstruction) -> u32 {
+    if a.ty != b.ty {
+        return 0;
+    }
+    2 + u32::from(a.dest == b.dest) + u32::from(a.src == b.src)
+}
+
+thread_local! {
+    /// `Homologous`'s alignment table, kept for the next child so long genomes don't cost a fresh
+    /// (a.len()+1)*(b.len()+1) allocation each time
+    static ALIGNMENT: RefCell<Vec<u32>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Lines the parents up so the most alike instructions are across from each other, keeping the
+/// order of each, then takes one gene of each lined up pair with a coin flip and each gene across
+/// from nothing with another.
+#[derive(Debug)]
+pub struct Homologous;
+
+impl Crossover for Homologous {
+    fn cross(&self, a: &[Gene], b: &[Gene], rng: &mut StdRng) -> Vec<Gene> {
+        ALIGNMENT.with(|best| align_and_cross(a, b, &mut best.borrow_mut(), rng))
+    }
+}
+
+/// `Homologous::cross`, with the table to fill passed in
+fn align_and_cross(a: &[Gene], b: &[Gene], best: &mut Vec<u32>, rng: &mut StdRng) -> Vec<Gene> {
+    // best[i][j] is the most similarity lining up a[i..] with b[j..] can get
+    let width = b.len() + 1;
+    best.clear();
+    best.resize((a.len() + 1) * width, 0);
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            let pair = match similarity(a[i].ins, b[j].ins) {
+                0 => 0,
+                s => s + best[(i+1)*width + j+1],
+            };
+            best[i*width + j] = pair.max(best[(i+1)*width + j]).max(best[i*width + j+1]);
+        }
+    }
+
+    let mut genes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        let here = best[i*width + j];
+        if i < a.len() && j < b.len() && similarity(a[i].ins, b[j].ins) != 0
+            && here == similarity(a[i].ins, b[j].ins) + best[(i+1)*width + j+1] {
+            genes.push(if rng.gen::<bool>() { a[i] } else { b[j] });
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || here == best[(i+1)*width + j]) {
+            if rng.gen::<bool>() { genes.push(a[i]) }
+            i += 1;
+        } else {
+            if rng.gen::<bool>() { genes.push(b[j]) }
+            j += 1;
+        }
+    }
+    genes.sort();
+    genes.dedup();
+    genes
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum CrossoverTy {
+    Diff,
+    OnePoint,
+    TwoPoint,
+    Uniform,
+    Homologous,
+}
+
+impl FromStr for CrossoverTy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "diff" => Ok(CrossoverTy::Diff),
+            "one-point" => Ok(CrossoverTy::OnePoint),
+            "two-point" => Ok(CrossoverTy::TwoPoint),
+            "uniform" => Ok(CrossoverTy::Uniform),
+            "homologous" => Ok(CrossoverTy::Homologous),
+            _ => Err(format!("Unknown crossover {:?}, expected diff, one-point, two-point, uniform or homologous", s)),
+        }
+    }
+}
+
+pub fn from_opts(opts: &EvolveOpts) -> Box<dyn Crossover> {
+    match opts.crossover {
+        CrossoverTy::Diff => Box::new(Diff),
+        CrossoverTy::OnePoint => Box::new(OnePoint),
+        CrossoverTy::TwoPoint => Box::new(TwoPoint),
+        CrossoverTy::Uniform => Box::new(Uniform),
+        CrossoverTy::Homologous => Box::new(Homologous),
+    }
+}
+
+#[cfg(test)]
+mod crossover_test {
+    use super::*;
+    use rand::SeedableRng;
+    use crate::svm::SvmConfig;
+
+    fn all() -> Vec<Box<dyn Crossover>> {
+        vec![Box::new(Diff), Box::new(OnePoint), Box::new(TwoPoint), Box::new(Uniform), Box::new(Homologous)]
+    }
+
+    fn random_genes(rng: &mut StdRng, len: usize) -> Vec<Gene> {
+        let config = SvmConfig{ num_registers: 4, ..SvmConfig::original(2) };
+        let mut genes:Vec<Gene> = (0..len).map(|_| Gene{order: rng.gen(), ins: SvmInstruction::random(rng, &config)}).collect();
+        genes.sort();
+        genes
+    }
+
+    #[test]
+    fn diff_matches_old_merge() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..50 {
+            let (a, b) = (random_genes(&mut rng, 20), random_genes(&mut rng, 25));
+            let mut old_rng = StdRng::seed_from_u64(1);
+            let mut old = Vec::new();
+            for mut block in diff(a.iter().copied(), b.iter().copied()) {
+                match block.ty {
+                    DiffTy::Both => old.append(&mut block.block),
+                    _ => if old_rng.gen::<bool>() { old.append(&mut block.block) },
+                }
+            }
+            assert_eq!(Diff.cross(&a, &b, &mut StdRng::seed_from_u64(1)), old);
+        }
+    }
+
+    #[test]
+    fn children_come_from_parents() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for crossover in all() {
+            for len in [0, 1, 5, 40] {
+                let (a, b) = (random_genes(&mut rng, len), random_genes(&mut rng, 30));
+                let child = crossover.cross(&a, &b, &mut rng);
+                assert!(child.windows(2).all(|w| w[0] <= w[1]), "{:?} isn't sorted", crossover);
+                assert!(child.iter().all(|g| a.contains(g) || b.contains(g)), "{:?}", crossover);
+                assert!(child.len() <= a.len() + b.len());
+            }
+            assert_eq!(crossover.cross(&[], &[], &mut rng), vec![]);
+        }
+    }
+
+    #[test]
+    fn same_parents_same_child() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for crossover in all() {
+            let a = random_genes(&mut rng, 30);
+            assert_eq!(crossover.cross(&a, &a, &mut rng), a, "{:?}", crossover);
+        }
+    }
+
+    #[test]
+    fn one_point_takes_ends() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let (a, b) = (random_genes(&mut rng, 30), random_genes(&mut rng, 30));
+        let child = OnePoint.cross(&a, &b, &mut rng);
+        let from_a = child.iter().take_while(|g| a.contains(g)).count();
+        assert!(child[from_a..].iter().all(|g| b.contains(g)));
+    }
+
+    #[test]
+    fn homologous_lines_up_alike_instructions() {
+        let gene = |order: f64, s: &str| Gene{order, ins: s.parse().unwrap()};
+        let a = vec![gene(0.1, "xor 0 1"), gene(0.2, "add 2 3"), gene(0.3, "shl 0 4")];
+        let b = vec![gene(0.15, "ldi 5 5"), gene(0.25, "xor 0 1"), gene(0.35, "shl 0 4")];
+        // xor and shl line up, so whatever the flips each appears once, from either parent
+        for seed in 0..20 {
+            let child = Homologous.cross(&a, &b, &mut StdRng::seed_from_u64(seed));
+            let xors = child.iter().filter(|g| g.ins == a[0].ins).count();
+            let shls = child.iter().filter(|g| g.ins == a[2].ins).count();
+            assert_eq!((xors, shls), (1, 1), "{:?}", child);
+        }
+        // the table left over from bigger parents doesn't change the next child
+        let fresh = Homologous.cross(&a, &b, &mut StdRng::seed_from_u64(7));
+        let long:Vec<Gene> = (0..50).map(|i| gene(f64::from(i) / 50.0, "xor 0 1")).collect();
+        Homologous.cross(&long, &long, &mut StdRng::seed_from_u64(0));
+        assert_eq!(Homologous.cross(&a, &b, &mut StdRng::seed_from_u64(7)), fresh);
+    }
+}
@@ -0,0 +1,347 @@
+// Binary file formats. Both start with a 4 byte magic and a little-endian u16 version; every
+// number after that is little-endian too.
+//
+// Checkpoint (magic "FWSC"), version 7:
+//   round u64, size u32, has_seed u8, seed u64 (0 if has_seed is 0), svm config, features u8,
+//   islands u32, pool length u32, then per genome: mutation_rate f64, 6 point mutation rates f64,
+//   fitness f64, 3 objectives f64, genes; then the Pareto front: a u32 count and that many u32
+//   positions in the pool
+// Program (magic "FWSP"), version 7:
+//   size u32, svm config, features u8, genes
+//
+// where the svm config is num_registers u16, output u8, first_input u8, num_inputs u16, then the
+// instruction set: a u32 with a bit per enabled opcode and the step budget as a u32. features
+// has a bit per input feature. genes is a u32 count followed by that many 11 byte genes: order
+// f64 then the 3 byte `SvmInstruction::encode` form.
+//
+// Version 1 had no svm config and version 2 only the instruction set; the rest is read as the
+// original layout. Before version 4 there were no input features, and before version 5 no point
+// mutation rates, which read as 0. Before version 6 the pool was a single island, and before
+// version 7 there were no objectives, which read as 0, or front, which reads as empty.
+
+use std::io::{self, Read, Write};
+
+use super::features::Features;
+use super::genetics::Gene;
+use super::mutation::PointRates;
+use super::pareto::Objectives;
+use super::svm::{InstructionSet, SvmConfig, SvmInstruction};
+use super::{Genome, State};
+
+pub const CHECKPOINT_MAGIC:&[u8; 4] = b"FWSC";
+pub const PROGRAM_MAGIC:&[u8; 4] = b"FWSP";
+const VERSION:u16 = 7;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u8(w: &mut impl Write, n: u8) -> io::Result<()> { w.write_all(&[n]) }
+fn write_u16(w: &mut impl Write, n: u16) -> io::Result<()> { w.write_all(&n.to_le_bytes()) }
+fn write_u32(w: &mut impl Write, n: u32) -> io::Result<()> { w.write_all(&n.to_le_bytes()) }
+fn write_u64(w: &mut impl Write, n: u64) -> io::Result<()> { w.write_all(&n.to_le_bytes()) }
+fn write_f64(w: &mut impl Write, n: f64) -> io::Result<()> { w.write_all(&n.to_le_bytes()) }
+
+fn read_bytes<const L: usize>(r: &mut impl Read) -> io::Result<[u8; L]> {
+    let mut buf = [0u8; L];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+fn read_u8(r: &mut impl Read) -> io::Result<u8> { Ok(read_bytes::<1>(r)?[0]) }
+fn read_u16(r: &mut impl Read) -> io::Result<u16> { Ok(u16::from_le_bytes(read_bytes(r)?)) }
+fn read_u32(r: &mut impl Read) -> io::Result<u32> { Ok(u32::from_le_bytes(read_bytes(r)?)) }
+fn read_u64(r: &mut impl Read) -> io::Result<u64> { Ok(u64::from_le_bytes(read_bytes(r)?)) }
+fn read_f64(r: &mut impl Read) -> io::Result<f64> { Ok(f64::from_le_bytes(read_bytes(r)?)) }
+
+fn write_header(w: &mut impl Write, magic: &[u8; 4]) -> io::Result<()> {
+    w.write_all(magic)?;
+    write_u16(w, VERSION)
+}
+
+fn read_header(r: &mut impl Read, magic: &[u8; 4]) -> io::Result<u16> {
+    if &read_bytes::<4>(r)? != magic {
+        return Err(invalid(format!("Expected a file starting with {:?}", std::str::from_utf8(magic).unwrap())));
+    }
+    let version = read_u16(r)?;
+    if version == 0 || version > VERSION {
+        return Err(invalid(format!("Unsupported file version {}", version)));
+    }
+    Ok(version)
+}
+
+fn write_config(w: &mut impl Write, config: &SvmConfig) -> io::Result<()> {
+    write_u16(w, config.num_registers as u16)?;
+    write_u8(w, config.output)?;
+    write_u8(w, config.first_input)?;
+    write_u16(w, config.num_inputs as u16)?;
+    write_u32(w, config.instruction_set.bits())?;
+    write_u32(w, config.instruction_set.step_budget)
+}
+
+/// `size` is the word size, which older versions had one input register for each letter of.
+fn read_config(r: &mut impl Read, version: u16, size: usize) -> io::Result<SvmConfig> {
+    let mut config = SvmConfig::original(size);
+    if version >= 3 {
+        config.num_registers = read_u16(r)?.into();
+        config.output = read_u8(r)?;
+        config.first_input = read_u8(r)?;
+        config.num_inputs = read_u16(r)?.into();
+    }
+    if version >= 2 {
+        let bits = read_u32(r)?;
+        config.instruction_set = InstructionSet::from_bits(bits, read_u32(r)?).map_err(invalid)?;
+    }
+    config.validate().map_err(invalid)?;
+    Ok(config)
+}
+
+fn write_genes(w: &mut impl Write, genes: &[Gene]) -> io::Result<()> {
+    write_u32(w, genes.len() as u32)?;
+    for gene in genes {
+        write_f64(w, gene.order)?;
+        w.write_all(&gene.ins.encode())?;
+    }
+    Ok(())
+}
+
+fn read_features(r: &mut impl Read, version: u16, size: usize, config: &SvmConfig) -> io::Result<Features> {
+    let features = if version >= 4 { Features::from_bits(read_u8(r)?).map_err(invalid)? } else { Features::default() };
+    if features.num_inputs(size) != config.num_inputs {
+        return Err(invalid(format!("{} inputs for {} letters and {}", config.num_inputs, size, features)));
+    }
+    Ok(features)
+}
+
+fn read_genes(r: &mut impl Read, config: &SvmConfig) -> io::Result<Vec<Gene>> {
+    let len = read_u32(r)? as usize;
+    let mut genes = Vec::new();
+    for _ in 0..len {
+        let order = read_f64(r)?;
+        let ins = SvmInstruction::decode(read_bytes(r)?).map_err(invalid)?;
+        genes.push(Gene{order, ins});
+    }
+    config.check_program(genes.iter().map(|g| g.ins)).map_err(invalid)?;
+    Ok(genes)
+}
+
+pub fn write_checkpoint(w: &mut impl Write, state: &State) -> io::Result<()> {
+    write_header(w, CHECKPOINT_MAGIC)?;
+    write_u64(w, state.round as u64)?;
+    write_u32(w, state.size as u32)?;
+    write_u8(w, state.seed.is_some() as u8)?;
+    write_u64(w, state.seed.unwrap_or_default())?;
+    write_config(w, &state.svm)?;
+    write_u8(w, state.features.bits())?;
+    write_u32(w, state.islands as u32)?;
+    write_u32(w, state.pool.len() as u32)?;
+    for genome in &state.pool {
+        write_f64(w, genome.mutation_rate)?;
+        for rate in &genome.point_rates.0 {
+            write_f64(w, *rate)?;
+        }
+        write_f64(w, genome.fitness)?;
+        for objective in &genome.objectives.0 {
+            write_f64(w, *objective)?;
+        }
+        write_genes(w, &genome.instructions)?;
+    }
+    write_u32(w, state.front.len() as u32)?;
+    for i in &state.front {
+        write_u32(w, *i as u32)?;
+    }
+    Ok(())
+}
+
+pub fn read_checkpoint(r: &mut impl Read) -> io::Result<State> {
+    let version = read_header(r, CHECKPOINT_MAGIC)?;
+    let round = read_u64(r)? as usize;
+    let size = read_u32(r)? as usize;
+    let has_seed = read_u8(r)? != 0;
+    let seed = read_u64(r)?;
+    let svm = read_config(r, version, size)?;
+    let features = read_features(r, version, size, &svm)?;
+    let islands = if version >= 6 { read_u32(r)? as usize } else { 1 };
+    let pool_len = read_u32(r)? as usize;
+    if islands == 0 || !pool_len.is_multiple_of(islands) {
+        return Err(invalid(format!("A pool of {} can't be split into {} islands", pool_len, islands)));
+    }
+    let mut pool = Vec::new();
+    for _ in 0..pool_len {
+        let mutation_rate = read_f64(r)?;
+        let mut point_rates = PointRates::default();
+        if version >= 5 {
+            for rate in &mut point_rates.0 {
+                *rate = read_f64(r)?;
+            }
+        }
+        let fitness = read_f64(r)?;
+        let mut objectives = Objectives::default();
+        if version >= 7 {
+            for objective in &mut objectives.0 {
+                *objective = read_f64(r)?;
+            }
+        }
+        let instructions = read_genes(r, &svm)?;
+        pool.push(Genome{ instructions, mutation_rate, point_rates, fitness, objectives });
+    }
+    let mut front = Vec::new();
+    if version >= 7 {
+        for _ in 0..read_u32(r)? {
+            let i = read_u32(r)? as usize;
+            if i >= pool_len {
+                return Err(invalid(format!("Front has genome {} of a pool of {}", i, pool_len)));
+            }
+            front.push(i);
+        }
+    }
+    Ok(State{
+        round,
+        size,
+        seed: if has_seed { Some(seed) } else { None },
+        svm,
+        features,
+        islands,
+        pool,
+        front,
+    })
+}
+
+pub fn write_program(w: &mut impl Write, size: usize, config: &SvmConfig, features: Features, genes: &[Gene]) -> io::Result<()> {
+    write_header(w, PROGRAM_MAGIC)?;
+    write_u32(w, size as u32)?;
+    write_config(w, config)?;
+    write_u8(w, features.bits())?;
+    write_genes(w, genes)
+}
+
+/// Returns the word size the program was evolved for, the machine it runs on, what its inputs
+/// hold and its genes.
+pub fn read_program(r: &mut impl Read) -> io::Result<(usize, SvmConfig, Features, Vec<Gene>)> {
+    let version = read_header(r, PROGRAM_MAGIC)?;
+    let size = read_u32(r)? as usize;
+    let config = read_config(r, version, size)?;
+    let features = read_features(r, version, size, &config)?;
+    let genes = read_genes(r, &config)?;
+    Ok((size, config, features, genes))
+}
+
+#[cfg(test)]
+mod encode_test {
+    use super::*;
+    use crate::svm::SvmInstructionTy;
+
+    fn genes() -> Vec<Gene> {
+        (0..20u8).map(|op| Gene{
+            order: f64::from(op) / 20.0,
+            ins: SvmInstruction{ty: SvmInstructionTy::from_opcode(op).unwrap(), dest: op, src: 31 - op},
+        }).collect()
+    }
+
+    #[test]
+    fn opcodes_round_trip() {
+        for op in 0..=255u8 {
+            if let Some(ty) = SvmInstructionTy::from_opcode(op) {
+                assert_eq!(ty.opcode(), op);
+            }
+        }
+        for gene in genes() {
+            assert_eq!(SvmInstruction::decode(gene.ins.encode()), Ok(gene.ins));
+        }
+        assert!(SvmInstruction::decode([20, 0, 0]).is_err());
+        assert!(SvmInstruction::decode([6, 0, 32]).is_err());
+        assert!(SvmInstruction::decode([0, 32, 200]).is_ok());
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let features = Features::parse_list("filled,successors").unwrap();
+        let mut svm = SvmConfig::original(features.num_inputs(7));
+        svm.num_registers = 40;
+        svm.output = 39;
+        svm.instruction_set = InstructionSet::all();
+        svm.instruction_set.step_budget = 77;
+        let state = State{
+            round: 48,
+            size: 7,
+            seed: Some(u64::MAX),
+            svm,
+            features,
+            islands: 2,
+            pool: vec![
+                Genome{
+                    instructions: genes(),
+                    mutation_rate: 0.25,
+                    point_rates: PointRates([0.1, 0.2, 0.3, 0.4, 0.5, 0.6]),
+                    fitness: 11216.8,
+                    objectives: Objectives([0.5, 1.25, 20.0]),
+                },
+                Genome{
+                    instructions: vec![],
+                    mutation_rate: 0.5,
+                    point_rates: PointRates::default(),
+                    fitness: -1.0,
+                    objectives: Objectives::default(),
+                },
+            ],
+            front: vec![1, 0],
+        };
+        let mut buf = Vec::new();
+        write_checkpoint(&mut buf, &state).unwrap();
+        let read = read_checkpoint(&mut buf.as_slice()).unwrap();
+        assert_eq!((read.round, read.size, read.seed), (state.round, state.size, state.seed));
+        assert_eq!((read.svm, read.features, read.islands), (state.svm, state.features, state.islands));
+        assert_eq!(read.front, state.front);
+        assert_eq!(read.pool.len(), 2);
+        for (a, b) in read.pool.iter().zip(&state.pool) {
+            assert_eq!(a.instructions, b.instructions);
+            assert_eq!((a.mutation_rate, a.point_rates, a.fitness), (b.mutation_rate, b.point_rates, b.fitness));
+            assert_eq!(a.objectives, b.objectives);
+        }
+
+        // a pool that can't be split evenly
+        let state = State{ islands: 3, ..state };
+        let mut buf = Vec::new();
+        write_checkpoint(&mut buf, &state).unwrap();
+        assert!(read_checkpoint(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn program_round_trip() {
+        let mut buf = Vec::new();
+        let mut config = SvmConfig::original(10);
+        config.instruction_set = InstructionSet::covering(genes().iter().map(|g| g.ins));
+        write_program(&mut buf, 10, &config, Features::default(), &genes()).unwrap();
+        assert_eq!(buf.len(), 4 + 2 + 4 + 14 + 1 + 4 + 20 * 11);
+        assert_eq!(read_program(&mut buf.as_slice()).unwrap(), (10, config, Features::default(), genes()));
+        assert!(read_checkpoint(&mut buf.as_slice()).is_err());
+
+        // features that don't fit the number of inputs
+        let mut buf = Vec::new();
+        write_program(&mut buf, 10, &config, Features::parse_list("used").unwrap(), &genes()).unwrap();
+        assert!(read_program(&mut buf.as_slice()).is_err());
+
+        // a gene count far bigger than the file, as a corrupt one might be
+        let mut buf = Vec::new();
+        write_program(&mut buf, 10, &config, Features::default(), &genes()).unwrap();
+        buf[25..29].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_program(&mut buf.as_slice()).is_err());
+
+        // genes using registers the machine doesn't have
+        let mut buf = Vec::new();
+        config.num_registers = 20;
+        write_program(&mut buf, 10, &config, Features::default(), &genes()).unwrap();
+        assert!(read_program(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(PROGRAM_MAGIC);
+        buf.extend_from_slice(&1u16.to_le_bytes());
+        buf.extend_from_slice(&10u32.to_le_bytes());
+        write_genes(&mut buf, &genes()[..12]).unwrap();
+        let (size, config, features, read) = read_program(&mut buf.as_slice()).unwrap();
+        assert_eq!((size, config, features), (10, SvmConfig::original(10), Features::default()));
+        assert_eq!(read, &genes()[..12]);
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+
+use super::*;
+
+/// Totals for every partial word in the map with the same number of filled letters.
+#[derive(Debug,Clone,Copy,Default)]
+pub struct DepthScore {
+    pub words: usize,
+    /// Partial words where every letter was guessed right
+    pub exact: usize,
+    pub false_positives: u64,
+    pub false_negatives: u64,
+    /// Expected `fitness_single` of a trial at this depth, weighting each partial word by how
+    /// likely `random_partial_word` is to pick it
+    pub fitness: f64,
+}
+
+impl DepthScore {
+    fn add(&mut self, other: &DepthScore) {
+        self.words += other.words;
+        self.exact += other.exact;
+        self.false_positives += other.false_positives;
+        self.false_negatives += other.false_negatives;
+    }
+
+    /// Fraction of letter decisions (allowed or not) that were right
+    pub fn accuracy(&self) -> f64 {
+        let letters = (self.words * 26) as f64;
+        (letters - (self.false_positives + self.false_negatives) as f64) / letters
+    }
+}
+
+/// Score of a predictor over every partial word in a `WMap`, indexed by number of filled letters.
+#[derive(Debug,Clone)]
+pub struct ExactScore {
+    pub depths: Vec<DepthScore>,
+}
+
+impl ExactScore {
+    /// The value `fitness` converges to as the number of trials grows: `random_partial_word`
+    /// picks a depth in 2..N uniformly and then walks down the map.
+    pub fn fitness(&self, num_instructions: usize, opts: &FitnessOpts) -> f64 {
+        let sampled = &self.depths[2..];
+        sampled.iter().map(|d| d.fitness).sum::<f64>() / (sampled.len() as f64)
+            + (num_instructions as f64) * opts.weight_instruction
+    }
+
+    pub fn total(&self) -> DepthScore {
+        let mut total = DepthScore::default();
+        for d in &self.depths {
+            total.add(d);
+        }
+        total
+    }
+}
+
+impl fmt::Display for ExactScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>5} {:>8} {:>8} {:>10} {:>10} {:>9} {:>16}", "depth", "words", "exact", "false pos", "false neg", "accuracy", "fitness")?;
+        for (depth, d) in self.depths.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>5} {:>8} {:>8} {:>10} {:>10} {:>8.4}% {:>16.5}",
+                depth, d.words, d.exact, d.false_positives, d.false_negatives, d.accuracy()*100.0, d.fitness,
+            )?;
+        }
+        let t = self.total();
+        write!(
+            f,
+            "{:>5} {:>8} {:>8} {:>10} {:>10} {:>8.4}%",
+            "total", t.words, t.exact, t.false_positives, t.false_negatives, t.accuracy()*100.0,
+        )
+    }
+}
+
+/// Probability of `random_partial_word` walking to `word`, given that it picked this depth.
+pub fn walk_probability<const N: usize>(map: &WMap<N>, word: &OWord<N>, depth: usize) -> f64 {
+    let mut probability = 1.0;
+    let mut prefix:OWord<N> = [None; N];
+    for i in 0..depth {
+        // random_partial_word picks the first letter from the whole alphabet
+        let options = if i == 0 { CharSet::full() } else { map[&prefix] };
+        probability /= options.into_iter().count() as f64;
+        prefix[i] = word[i];
+    }
+    probability
+}
+
+/// Scores `predict` on every partial word in the map. `predict` returns the guessed set of allowed
+/// next letters in the same form as the SVM output register.
+pub fn exact_score<const N: usize>(
+    map: &WMap<N>,
+    opts: &FitnessOpts,
+    predict: impl Fn(&OWord<N>) -> u32,
+) -> ExactScore {
+    let mut depths = vec![DepthScore::default(); N];
+    for (word, set) in map {
+        let depth = word.iter().take_while(|c| c.is_some()).count();
+        let guess = predict(word);
+        let (false_positives, false_negatives) = guess_errors(guess, (*set).into());
+
+        let probability = walk_probability(map, word, depth);
+
+        let d = &mut depths[depth];
+        d.words += 1;
+        if false_positives == 0 && false_negatives == 0 {
+            d.exact += 1;
+        }
+        d.false_positives += u64::from(false_positives);
+        d.false_negatives += u64::from(false_negatives);
+        d.fitness += probability * trial_score(false_positives, false_negatives, opts);
+    }
+    ExactScore{ depths }
+}
+
+/// Partial words shown in `Certificate`
+const CERTIFICATE_EXAMPLES:usize = 10;
+
+/// Whether a predictor ever leaves out a letter that's allowed, checked on every partial word in
+/// the map rather than a sample, since a filter for the square search that does is no use.
+#[derive(Debug,Clone)]
+pub struct Certificate<const N: usize> {
+    pub words: usize,
+    /// Partial words with at least one false negative
+    pub failures: usize,
+    /// The first few of those in order, with the letters left out
+    pub examples: Vec<(OWord<N>, u32)>,
+}
+
+impl<const N: usize> Certificate<N> {
+    pub fn certified(&self) -> bool {
+        self.failures == 0
+    }
+}
+
+pub fn certify<const N: usize>(map: &WMap<N>, predict: impl Fn(&OWord<N>) -> u32) -> Certificate<N> {
+    let mut failures:Vec<(OWord<N>, u32)> = Vec::new();
+    for (word, set) in map {
+        let real:u32 = (*set).into();
+        let missed = real & !predict(word);
+        if missed != 0 {
+            failures.push((*word, missed));
+        }
+    }
+    failures.sort_unstable_by_key(|(word, _)| *word);
+    Certificate{
+        words: map.len(),
+        failures: failures.len(),
+        examples: failures.into_iter().take(CERTIFICATE_EXAMPLES).collect(),
+    }
+}
+
+impl<const N: usize> fmt::Display for Certificate<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.certified() {
+            return write!(f, "Certified: no false negatives on any of the {} partial words", self.words);
+        }
+        write!(f, "Not certified: false negatives on {} of the {} partial words", self.failures, self.words)?;
+        for (word, missed) in &self.examples {
+            let word:String = word.iter().map(|c| c.map_or('.', char::from)).collect();
+            write!(f, "\n  {} missed {}", word, letters(*missed))?;
+        }
+        if self.failures > self.examples.len() {
+            write!(f, "\n  ...")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod exact_test {
+    use super::*;
+
+    fn map() -> WMap<4> {
+        let words:Vec<Word<4>> = ["abed", "aces", "bead", "beds"].iter()
+            .map(|w| parse_oword::<4>(w).unwrap().map(Option::unwrap))
+            .collect();
+        build_map(&words)
+    }
+
+    #[test]
+    fn certify_everything_allowed() {
+        let map = map();
+        assert!(certify(&map, |_| u32::from(CharSet::full())).certified());
+        let certificate = certify(&map, |_| 0);
+        assert_eq!((certificate.words, certificate.failures), (map.len(), map.len()));
+    }
+
+    #[test]
+    fn certify_finds_the_one_miss() {
+        let map = map();
+        // right everywhere but "be", where it leaves out the d of "beds"
+        let be = parse_oword::<4>("be").unwrap();
+        let certificate = certify(&map, |word| {
+            let real = u32::from(map[word]);
+            if *word == be { real & !(1 << 4) } else { real }
+        });
+        assert_eq!(certificate.failures, 1);
+        assert_eq!(certificate.examples, vec![(be, 1 << 4)]);
+        assert_eq!(certificate.to_string(), format!("Not certified: false negatives on 1 of the {} partial words\n  be.. missed d", map.len()));
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+
+use super::*;
+
+/// Something worked out from the whole partial word and put in an input register after the
+/// letters, so programs don't have to rediscover it from the letters themselves.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Feature {
+    /// Number of filled letters
+    Filled,
+    /// Every letter used so far, as a `CharSet`
+    Used,
+    /// Bit per position, set if that position holds a vowel
+    Vowels,
+    /// Letters that ever follow the letter before the first unfilled one, as a `CharSet`, from
+    /// the word map. With no letters filled, the letters words start with.
+    Successors,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 4] = [Feature::Filled, Feature::Used, Feature::Vowels, Feature::Successors];
+
+    fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Filled => "filled",
+            Feature::Used => "used",
+            Feature::Vowels => "vowels",
+            Feature::Successors => "successors",
+        }
+    }
+}
+
+/// Which features a machine gets as inputs. Stored with checkpoints, since a program only makes
+/// sense with the inputs it was evolved on.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct Features(u8);
+
+impl Features {
+    pub fn contains(&self, feature: Feature) -> bool {
+        self.0 & feature.bit() != 0
+    }
+
+    /// In the order their input registers come in
+    pub fn iter(self) -> impl Iterator<Item=Feature> {
+        let all:&'static [Feature] = &Feature::ALL;
+        all.iter().copied().filter(move |f| self.contains(*f))
+    }
+
+    /// Input registers a word of `size` letters takes: one per letter then one per feature
+    pub fn num_inputs(&self, size: usize) -> usize {
+        size + self.iter().count()
+    }
+
+    /// Bit per feature, as stored in files
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u8) -> Result<Self, String> {
+        let all = Feature::ALL.iter().fold(0, |bits, f| bits | f.bit());
+        if bits & !all != 0 {
+            return Err(format!("Invalid input features {:#x}", bits));
+        }
+        Ok(Features(bits))
+    }
+
+    /// Parses a comma separated list of feature names, or "all".
+    pub fn parse_list(list: &str) -> Result<Self, String> {
+        let mut features = Features::default();
+        for name in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            if name.eq_ignore_ascii_case("all") {
+                features = Feature::ALL.iter().fold(features, |fs, f| Features(fs.0 | f.bit()));
+                continue;
+            }
+            match Feature::ALL.iter().find(|f| f.name().eq_ignore_ascii_case(name)) {
+                Some(f) => features.0 |= f.bit(),
+                None => return Err(format!("Unknown input feature {:?}", name)),
+            }
+        }
+        Ok(features)
+    }
+}
+
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names:Vec<&str> = self.iter().map(Feature::name).collect();
+        if names.is_empty() {
+            write!(f, "letters")
+        } else {
+            write!(f, "letters+{}", names.join(","))
+        }
+    }
+}
+
+/// What an input register holds for a letter: just that letter's bit, or 0 if it isn't filled.
+pub fn letter_input(letter: Option<FChar>) -> u32 {
+    letter.map(|f| u32::from(CharSet::default().set(f))).unwrap_or_default()
+}
+
+fn is_vowel(letter: FChar) -> bool {
+    matches!(char::from(letter), 'a' | 'e' | 'i' | 'o' | 'u')
+}
+
+/// Turns partial words into the values of a machine's input registers.
+pub struct InputEncoder {
+    features: Features,
+    /// `Feature::Successors` for each letter, and at 0 for no letter
+    successors: [u32; 27],
+}
+
+impl InputEncoder {
+    pub fn new<const N: usize>(features: Features, map: &WMap<N>) -> Self {
+        let mut successors = [0u32; 27];
+        for (word, set) in map {
+            let filled = word.iter().take_while(|c| c.is_some()).count();
+            let prev = if filled == 0 { 0 } else { u8::from(word[filled-1].unwrap()) };
+            successors[usize::from(prev)] |= u32::from(*set);
+        }
+        InputEncoder{ features, successors }
+    }
+
+    /// Fills `inputs`, which must be `features.num_inputs(N)` long.
+    pub fn encode<const N: usize>(&self, word: &OWord<N>, inputs: &mut [u32]) {
+        assert_eq!(inputs.len(), self.features.num_inputs(N));
+        for (input, letter) in inputs.iter_mut().zip(word) {
+            *input = letter_input(*letter);
+        }
+        for (input, feature) in inputs[N..].iter_mut().zip(self.features.iter()) {
+            *input = match feature {
+                Feature::Filled => word.iter().filter(|c| c.is_some()).count() as u32,
+                Feature::Used => word.iter().fold(0, |used, c| used | letter_input(*c)),
+                Feature::Vowels => word.iter().enumerate()
+                    .filter(|(_, c)| matches!(c, Some(c) if is_vowel(*c)))
+                    .fold(0, |mask, (i, _)| mask | (1 << i)),
+                Feature::Successors => {
+                    let filled = word.iter().take_while(|c| c.is_some()).count();
+                    let prev = if filled == 0 { 0 } else { u8::from(word[filled-1].unwrap()) };
+                    self.successors[usize::from(prev)]
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod features_test {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(Features::default().to_string(), "letters");
+        let features = Features::parse_list("vowels, filled").unwrap();
+        assert_eq!(features.to_string(), "letters+filled,vowels");
+        assert_eq!(features.num_inputs(5), 7);
+        assert_eq!(Features::from_bits(features.bits()), Ok(features));
+        assert_eq!(Features::parse_list("all").unwrap().to_string(), "letters+filled,used,vowels,successors");
+        assert!(Features::parse_list("filled,bogus").is_err());
+        assert!(Features::from_bits(0x10).is_err());
+    }
+
+    #[test]
+    fn encode() {
+        let words:Vec<Word<4>> = ["abed", "aces", "bead", "odes"].iter()
+            .map(|w| parse_oword::<4>(w).unwrap().map(Option::unwrap))
+            .collect();
+        let encoder = InputEncoder::new(Features::parse_list("all").unwrap(), &build_map(&words));
+        let set = |s: &str| u32::from(s.chars().fold(CharSet::default(), |set, c| set.set(FChar::try_from(c).unwrap())));
+
+        let mut inputs = [0u32; 8];
+        encoder.encode(&parse_oword::<4>("ace").unwrap(), &mut inputs);
+        assert_eq!(inputs[..4], [set("a"), set("c"), set("e"), 0]);
+        assert_eq!(inputs[4..], [3, set("ace"), 0b101, set("ads")]);
+
+        encoder.encode(&parse_oword::<4>("").unwrap(), &mut inputs);
+        assert_eq!(inputs, [0, 0, 0, 0, 0, 0, 0, set("abo")]);
+    }
+}
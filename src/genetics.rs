@@ -85,6 +85,24 @@ pub fn diff(mut a_it:impl Iterator<Item=Gene>, mut b_it:impl Iterator<Item=Gene>
     res
 }
 
+/// Number of genes only in `a`, only in `b`, and in both: what the blocks `diff` returns add up
+/// to, without building them. Both have to be sorted.
+pub fn diff_sizes(a: &[Gene], b: &[Gene]) -> (usize, usize, usize) {
+    let (mut i, mut j, mut both) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Ordering::Equal => {
+                both += 1;
+                i += 1;
+                j += 1;
+            },
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+        }
+    }
+    (a.len() - both, b.len() - both, both)
+}
+
 #[cfg(test)]
 mod diff_test {
     use super::*;
@@ -96,6 +114,11 @@ mod diff_test {
         );
         let nums:Vec<_> = res.iter().map(|g| (g.ty, g.block.iter().map(|n| n.order).collect():Vec<_>)).collect();
         assert_eq!(nums.as_slice(), expected);
+
+        let a:Vec<Gene> = a.iter().map(|n| Gene{order: *n, ins}).collect();
+        let b:Vec<Gene> = b.iter().map(|n| Gene{order: *n, ins}).collect();
+        let size = |ty| expected.iter().filter(|(t, _)| *t == ty).map(|(_, block)| block.len()).sum::<usize>();
+        assert_eq!(diff_sizes(&a, &b), (size(DiffTy::A), size(DiffTy::B), size(DiffTy::Both)));
     }
     #[test]
     fn both_empty() {
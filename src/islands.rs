@@ -0,0 +1,154 @@
+use std::str::FromStr;
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use super::Genome;
+use super::pareto::Ranking;
+
+/// Which island each island's migrants go to
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Topology {
+    /// Island i sends to island i+1, and the last to the first
+    Ring,
+    /// Each island sends to another picked at random every time
+    Random,
+}
+
+impl FromStr for Topology {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ring" => Ok(Topology::Ring),
+            "random" => Ok(Topology::Random),
+            _ => Err(format!("Unknown migration topology {:?}, expected ring or random", s)),
+        }
+    }
+}
+
+impl Topology {
+    /// Where each of `islands` islands sends its migrants this time; never to itself.
+    pub fn destinations(self, islands: usize, rng: &mut StdRng) -> Vec<usize> {
+        match self {
+            Topology::Ring => (0..islands).map(|i| (i + 1) % islands).collect(),
+            Topology::Random => (0..islands).map(|i| {
+                let to = rng.gen_range(0..islands - 1);
+                if to >= i { to + 1 } else { to }
+            }).collect(),
+        }
+    }
+}
+
+/// Copies the best `size` genomes of each island over the worst of the one it sends to. All the
+/// migrants are picked before any arrive, so a genome moves at most one island each time.
+///
+/// `pool` is `islands` islands of the same length one after another, each sorted best first by
+/// `ranking`, and each is left sorted by it. A random topology can send several islands' migrants
+/// to the same one; past the island's length the rest are dropped.
+pub fn migrate(
+    pool: &mut [Genome],
+    islands: usize,
+    size: usize,
+    topology: Topology,
+    ranking: Ranking,
+    rng: &mut StdRng,
+) {
+    if islands < 2 || pool.is_empty() {
+        return;
+    }
+    let len = pool.len() / islands;
+    let size = size.min(len);
+    let to = topology.destinations(islands, rng);
+    let migrants:Vec<Vec<Genome>> = pool.chunks(len).map(|island| island[..size].to_vec()).collect();
+    let mut arrived = vec![0; islands];
+    for (from, genomes) in migrants.into_iter().enumerate() {
+        let island = &mut pool[to[from]*len..(to[from]+1)*len];
+        for genome in genomes {
+            if arrived[to[from]] == len {
+                break;
+            }
+            arrived[to[from]] += 1;
+            island[len - arrived[to[from]]] = genome;
+        }
+    }
+    for island in pool.chunks_mut(len) {
+        ranking.sort(island);
+    }
+}
+
+#[cfg(test)]
+mod islands_test {
+    use super::*;
+    use rand::SeedableRng;
+    use crate::mutation::PointRates;
+    use crate::pareto::Objectives;
+
+    /// 3 islands of 4, island i holding fitness 10i to 10i+3
+    fn pool() -> Vec<Genome> {
+        (0..12).map(|i| Genome{
+            instructions: vec![],
+            mutation_rate: 0.5,
+            point_rates: PointRates::default(),
+            fitness: f64::from((i / 4) * 10 + i % 4),
+            objectives: Objectives::default(),
+        }).collect()
+    }
+
+    fn fitness(pool: &[Genome]) -> Vec<f64> {
+        pool.iter().map(|g| g.fitness).collect()
+    }
+
+    #[test]
+    fn ring() {
+        let mut pool = pool();
+        migrate(&mut pool, 3, 2, Topology::Ring, Ranking::Fitness, &mut StdRng::seed_from_u64(0));
+        assert_eq!(fitness(&pool), vec![
+            0.0, 1.0, 20.0, 21.0,
+            0.0, 1.0, 10.0, 11.0,
+            10.0, 11.0, 20.0, 21.0,
+        ]);
+    }
+
+    #[test]
+    fn random_never_sends_to_itself() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for islands in 2..6 {
+            for _ in 0..50 {
+                let to = Topology::Random.destinations(islands, &mut rng);
+                assert!(to.iter().enumerate().all(|(from, to)| from != *to && *to < islands), "{:?}", to);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_sizes_and_order() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for size in [0, 1, 3, 4, 10] {
+            let mut pool = pool();
+            migrate(&mut pool, 3, size, Topology::Random, Ranking::Fitness, &mut rng);
+            assert_eq!(pool.len(), 12);
+            assert!(pool.chunks(4).all(|i| i.windows(2).all(|w| w[0].fitness <= w[1].fitness)));
+        }
+        // at most 2 arrive in an island of 4, so every island keeps its own best
+        let mut mixed = pool();
+        migrate(&mut mixed, 3, 1, Topology::Random, Ranking::Fitness, &mut rng);
+        for (island, best) in mixed.chunks(4).zip([0.0, 10.0, 20.0]) {
+            assert!(fitness(island).contains(&best), "{:?}", fitness(&mixed));
+        }
+        let mut one = pool();
+        migrate(&mut one, 1, 3, Topology::Ring, Ranking::Fitness, &mut rng);
+        assert_eq!(fitness(&one), fitness(&pool()));
+    }
+
+    #[test]
+    fn keeps_pareto_order() {
+        // each genome dominates the ones after it in its island, but fitness runs the other way
+        let mut pool:Vec<Genome> = (0..8).map(|i| {
+            let o = f64::from(i % 4) + f64::from(i / 4) * 0.5;
+            Genome{ fitness: 10.0 - o, objectives: Objectives([o, o, 1.0]), ..pool()[0].clone() }
+        }).collect();
+        migrate(&mut pool, 2, 1, Topology::Ring, Ranking::Pareto, &mut StdRng::seed_from_u64(0));
+        let objectives:Vec<f64> = pool.iter().map(|g| g.objectives.0[0]).collect();
+        assert_eq!(objectives, vec![0.0, 0.5, 1.0, 2.0, 0.0, 0.5, 1.5, 2.5]);
+    }
+}
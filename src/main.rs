@@ -7,11 +7,13 @@ use std::path::Path;
 use std::convert::TryFrom;
 use std::default::Default;
 use std::cmp::PartialOrd;
-use std::fmt;
 use fnv::FnvHashMap;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rayon::prelude::*;
 use serde_derive::{Serialize,Deserialize};
-use libflate::gzip::Encoder;
+use libflate::gzip::Decoder;
+use structopt::StructOpt;
 
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
 where P: AsRef<Path>, {
@@ -28,53 +30,239 @@ use svm::*;
 mod genetics;
 use genetics::*;
 
-const SIZE:usize = 10;
+mod cli;
+use cli::*;
 
-type Word = [FChar; SIZE];
-type OWord = [Option<FChar>; SIZE];
-type Square = [OWord; SIZE];
-type WMap = FnvHashMap<OWord, CharSet>;
+mod exact;
+use exact::exact_score;
 
+mod asm;
 
-const INSTRUCTION_SIZE_MAX:usize = 1024;
-const INSTRUCTION_SIZE_INIT:usize = 32;
-const GENEPOOL_SIZE:usize = 1000;
+mod encode;
 
-const WEIGHT_FALSE_POSITIVE:f64 = 1000.0;
-const WEIGHT_FALSE_NEGATIVE:f64 = 4000.0;
-const WEIGHT_INSTRUCTION:f64 = 0.1;
+mod optimize;
 
-const NUM_TRIALS:usize = 1000;
+mod compile;
+use compile::{CompiledProgram, BATCH_LANES, Lanes};
 
+mod features;
+use features::{Features, InputEncoder};
 
+mod baseline;
+use baseline::{DecisionTree, LetterTable};
 
-// Fitness function, lower is better
-fn fitness<R: Rng + ?Sized, I: Iterator<Item=SvmInstruction> + std::iter::ExactSizeIterator + fmt::Debug + Clone>(
-    instructions: I,
-    map: &WMap,
+mod selection;
+
+mod crossover;
+
+mod mutation;
+use mutation::PointRates;
+
+mod islands;
+
+mod niching;
+use niching::{DistanceTy, Niching, Profile};
+
+mod pareto;
+use pareto::Objectives;
+
+mod search;
+
+type Word<const N: usize> = [FChar; N];
+type OWord<const N: usize> = [Option<FChar>; N];
+type Square<const N: usize> = [OWord<N>; N];
+type WMap<const N: usize> = FnvHashMap<OWord<N>, CharSet>;
+
+
+#[derive(Debug,Clone,Serialize,Deserialize)]
+struct Genome {
+    instructions: Vec<Gene>,
+    mutation_rate: f64,
+    #[serde(default)]
+    point_rates: PointRates,
+    fitness: f64,
+    #[serde(default)]
+    objectives: Objectives,
+}
+
+impl Genome {
+    fn compile(&self, config: &SvmConfig) -> CompiledProgram {
+        CompiledProgram::compile(self.instructions.iter().map(|g| g.ins), config)
+    }
+}
+
+fn default_size() -> usize { 10 }
+
+fn default_svm() -> SvmConfig { SvmConfig::original(default_size()) }
+
+fn default_islands() -> usize { 1 }
+
+#[derive(Debug,Serialize,Deserialize)]
+struct State {
+    round: usize,
+    // checkpoints from before the word length was configurable were all 10 letters
+    #[serde(default = "default_size")]
+    size: usize,
+    #[serde(default)]
+    seed: Option<u64>,
+    // json checkpoints are all from before the machine was configurable, load_state fills this in
+    #[serde(skip, default = "default_svm")]
+    svm: SvmConfig,
+    #[serde(skip)]
+    features: Features,
+    /// The pool is this many islands of the same length one after another, each sorted best first
+    #[serde(skip, default = "default_islands")]
+    islands: usize,
+    pool: Vec<Genome>,
+    /// Positions in the pool of the genomes no other beats on every objective, as of when the pool
+    /// was last scored
+    #[serde(skip)]
+    front: Vec<usize>,
+}
+
+impl State {
+    fn island_len(&self) -> usize {
+        self.pool.len() / self.islands
+    }
+}
+
+/// Rng for one part of one round. Deriving it from the round number rather than carrying one rng
+/// through the whole run means a run resumed from a checkpoint draws the same numbers as one that
+/// never stopped.
+const STREAM_SHARED_TRIALS:u64 = u64::MAX;
+const STREAM_VALIDATION:u64 = u64::MAX - 1;
+const STREAM_MIGRATION:u64 = u64::MAX - 2;
+const STREAM_BEHAVIOR:u64 = u64::MAX - 3;
+
+/// Island 0 keeps stream 0, which the whole pool used before there were islands
+fn island_stream(island: usize) -> u64 {
+    if island == 0 { 0 } else { u64::MAX / 2 + island as u64 }
+}
+
+fn round_rng(seed: u64, round: usize, stream: u64) -> StdRng {
+    let mut bytes = [0u8; 32];
+    bytes[0..8].copy_from_slice(&seed.to_le_bytes());
+    bytes[8..16].copy_from_slice(&(round as u64).to_le_bytes());
+    bytes[16..24].copy_from_slice(&stream.to_le_bytes());
+    StdRng::from_seed(bytes)
+}
+
+fn save_state<P: AsRef<Path>>(filename: P, state: &State) -> io::Result<()> {
+    let mut f = io::BufWriter::new(File::create(filename)?);
+    encode::write_checkpoint(&mut f, state)?;
+    f.into_inner()?.sync_all()
+}
+
+/// Reads a binary checkpoint, an older gzipped json checkpoint, or a single program, which is
+/// loaded as a pool of one.
+fn load_state<P: AsRef<Path>>(filename: P) -> io::Result<State> {
+    let mut f = io::BufReader::new(File::open(filename)?);
+    let magic = f.fill_buf()?;
+    if magic.starts_with(encode::CHECKPOINT_MAGIC) {
+        encode::read_checkpoint(&mut f)
+    } else if magic.starts_with(encode::PROGRAM_MAGIC) {
+        let (size, svm, features, instructions) = encode::read_program(&mut f)?;
+        Ok(State{
+            round: 1,
+            size,
+            seed: None,
+            svm,
+            features,
+            islands: 1,
+            pool: vec![Genome{
+                instructions,
+                mutation_rate: 0.5,
+                point_rates: PointRates::default(),
+                fitness: 0.0,
+                objectives: Objectives::default(),
+            }],
+            front: Vec::new(),
+        })
+    } else {
+        let decoder = Decoder::new(f)?;
+        let mut state:State = serde_json::from_reader(decoder)?;
+        state.svm = SvmConfig::original(state.size);
+        Ok(state)
+    }
+}
+
+
+fn random_partial_word<const N: usize, R: Rng + ?Sized>(map: &WMap<N>, rng: &mut R) -> OWord<N> {
+    let num_to_fill = rng.gen_range(2..N); //purposefully NOT inclusive of completely filled
+    let mut word:OWord<N> = [None; N];
+    // only letters some word starts with, so every prefix drawn is in the map
+    let mut set = map[&word];
+    let mut num_filled = 0;
+    while num_filled < num_to_fill {
+        //dbg!(num_filled, num_to_fill, word, set);
+        let options:Vec<_> = set.into_iter().collect();
+        if options.is_empty() { panic!(); }
+        word[num_filled] = Some(options[rng.gen_range(0..options.len())]);
+        num_filled += 1;
+        set = *map.get(&word).unwrap();
+    }
+    // for i in 0..num_to_fill {
+    //     word[i] = Some(FChar::random(rng));
+    // }
+    word
+}
+
+// Fitness function, lower is better. Also returns the objectives the fitness weighs up.
+fn score<const N: usize, R: Rng + ?Sized>(
+    program: &CompiledProgram,
+    map: &WMap<N>,
+    inputs: &InputEncoder,
     rng: &mut R,
-    trials: usize
+    opts: &FitnessOpts,
+) -> (f64, Objectives) {
+    let words:Vec<OWord<N>> = (0..opts.trials).map(|_| random_partial_word(map, rng)).collect();
+    score_on(program, map, inputs, &words, opts)
+}
+
+/// Just the fitness of `score_on`
+fn fitness_on<const N: usize>(
+    program: &CompiledProgram,
+    map: &WMap<N>,
+    inputs: &InputEncoder,
+    words: &[OWord<N>],
+    opts: &FitnessOpts,
 ) -> f64 {
+    score_on(program, map, inputs, words, opts).0
+}
+
+/// Same as `score`, but scored on a given set of partial words instead of drawing new ones.
+fn score_on<const N: usize>(
+    program: &CompiledProgram,
+    map: &WMap<N>,
+    inputs: &InputEncoder,
+    words: &[OWord<N>],
+    opts: &FitnessOpts,
+) -> (f64, Objectives) {
     let mut sum = 0.0;
-    for _ in 0..trials {
-        let num_to_fill = rng.gen_range(2..SIZE); //purposefully NOT inclusive of completely filled
-        let mut word:OWord = [None; SIZE];
-        let mut set = CharSet::full();
-        let mut num_filled = 0;
-        while num_filled < num_to_fill {
-            //dbg!(num_filled, num_to_fill, word, set);
-            let options:Vec<_> = set.into_iter().collect();
-            if options.is_empty() { panic!(); }
-            word[num_filled] = Some(options[rng.gen_range(0..options.len())]);
-            num_filled += 1;
-            set = *map.get(&word).unwrap();
-        }
-        // for i in 0..num_to_fill {
-        //     word[i] = Some(FChar::random(rng));
-        // }
-        sum += fitness_single(instructions.clone(), map, word, false);
+    let (mut false_positives, mut false_negatives) = (0, 0);
+    for batch in words.chunks(BATCH_LANES) {
+        let guesses = run_program_batch(program, inputs, batch);
+        for (word, guess) in batch.iter().zip(guesses.iter()) {
+            let real:u32 = map.get(word).copied().unwrap_or_default().into();
+            let errors = guess_errors(*guess, real);
+            false_positives += errors.0;
+            false_negatives += errors.1;
+            sum += trial_score(errors.0, errors.1, opts);
+        }
     }
-    sum/(trials as f64) + (instructions.len() as f64) * WEIGHT_INSTRUCTION
+    let trials = words.len() as f64;
+    let instructions = program.num_instructions() as f64;
+    let objectives = Objectives([f64::from(false_positives) / trials, f64::from(false_negatives) / trials, instructions]);
+    (sum/trials + instructions * opts.weight_instruction, objectives)
+}
+
+/// The program's guess for each of `words`
+fn outputs_on<const N: usize>(program: &CompiledProgram, inputs: &InputEncoder, words: &[OWord<N>]) -> Vec<u32> {
+    let mut outputs = Vec::with_capacity(words.len());
+    for batch in words.chunks(BATCH_LANES) {
+        outputs.extend_from_slice(&run_program_batch(program, inputs, batch)[..batch.len()]);
+    }
+    outputs
 }
 
 // f full
@@ -94,31 +282,77 @@ fn fitness<R: Rng + ?Sized, I: Iterator<Item=SvmInstruction> + std::iter::ExactS
 // false positives = (g^r)&g
 // false negatives = (g^r)&r
 
+/// Returns the number of (false positives, false negatives) in a guess.
+fn guess_errors(guess: u32, real: u32) -> (u32, u32) {
+    let full:u32 = CharSet::full().into();
+    let xor = guess^real;
+    ((full&xor&guess).count_ones(), (full&xor&real).count_ones())
+}
 
-fn fitness_single<I: Iterator<Item=SvmInstruction> + fmt::Debug>(
-    instructions: I,
-    map: &WMap,
-    word: OWord,
-    debug: bool,
-) -> f64 {
-    let mut state = SvmState::new(instructions);
-    
-    for i in 0..SIZE {
-        state.memory_mut()[i+1] = word[i].map(|f| CharSet::default().set(f).into():u32).unwrap_or_default();
-    }
+fn trial_score(false_positives: u32, false_negatives: u32, opts: &FitnessOpts) -> f64 {
+    let false_positives:f64 = false_positives.into();
+    let false_negatives:f64 = false_negatives.into();
+    ((false_positives * opts.weight_false_positive) + (false_negatives * opts.weight_false_negative)).powf(2.0)
+}
+
+/// Runs a program on a partial word and returns what it left in the output register, which is its
+/// guess at the set of letters that can come next.
+fn run_program<const N: usize>(
+    program: &CompiledProgram,
+    inputs: &InputEncoder,
+    word: OWord<N>,
+) -> u32 {
+    let config = program.config();
     //Default to outputting all 0's (the worst default) to discourage empty programs
-    state.memory_mut()[0] = 0;
-    loop {
-        let res = state.step();
-        if res == StepResult::Finish { break; }
+    let mut regs = [0u32; SVM_MAX_REGISTERS];
+    let regs = &mut regs[..config.num_registers];
+    inputs.encode(&word, &mut regs[config.inputs()]);
+    program.run(regs);
+    regs[usize::from(config.output)]
+}
+
+/// `run_program` on up to `BATCH_LANES` words at once. Lanes past the end of `words` are junk.
+fn run_program_batch<const N: usize>(
+    program: &CompiledProgram,
+    inputs: &InputEncoder,
+    words: &[OWord<N>],
+) -> Lanes {
+    let config = program.config();
+    let mut regs = vec![[0u32; BATCH_LANES]; config.num_registers];
+    let mut values = vec![0u32; config.num_inputs];
+    for (lane, word) in words.iter().enumerate() {
+        inputs.encode(word, &mut values);
+        for (reg, value) in regs[config.inputs()].iter_mut().zip(&values) {
+            reg[lane] = *value;
+        }
     }
+    program.run_batch(&mut regs);
+    regs[usize::from(config.output)]
+}
+
+fn fitness_single<const N: usize>(
+    program: &CompiledProgram,
+    map: &WMap<N>,
+    inputs: &InputEncoder,
+    word: OWord<N>,
+    opts: &FitnessOpts,
+    debug: bool,
+) -> f64 {
+    guess_score(run_program(program, inputs, word), map, word, opts, debug)
+}
+
+fn guess_score<const N: usize>(
+    guess: u32,
+    map: &WMap<N>,
+    word: OWord<N>,
+    opts: &FitnessOpts,
+    debug: bool,
+) -> f64 {
     let full:u32 = CharSet::full().into();
-    let guess:u32 = state.memory_mut()[0];
     let real:u32 = map.get(&word).copied().unwrap_or_default().into();
-    let xor = guess^real;
-    let false_positives:f64 = (full&xor&guess).count_ones().into();
-    let false_negatives:f64 = (full&xor&real ).count_ones().into();
+    let (false_positives, false_negatives) = guess_errors(guess, real);
     if debug {
+        let xor = guess^real;
         dbg!(
             guess,
             real,
@@ -129,265 +363,729 @@ fn fitness_single<I: Iterator<Item=SvmInstruction> + fmt::Debug>(
             false_negatives,
         );
     }
-    ((false_positives * WEIGHT_FALSE_POSITIVE) + (false_negatives * WEIGHT_FALSE_NEGATIVE)).powf(2.0)
+    trial_score(false_positives, false_negatives, opts)
 }
 
-fn main() {
-    dbg!(SIZE);
-    let wordsfn = std::env::args().nth(1).unwrap_or_else(|| String::from("/home/shelvacu/words/uncompressible.txt"));
-
-    // let data = std::fs::read(&wordsfn).unwrap();
-
-    // dbg!(&data[0..10]);
-
-    let mut words:Vec<Word> = Vec::new();
-    for maybe_line in read_lines(&wordsfn).unwrap() {
+fn load_words<const N: usize, P: AsRef<Path>>(filename: P) -> Vec<Word<N>> {
+    let mut words:Vec<Word<N>> = Vec::new();
+    for maybe_line in read_lines(filename).unwrap() {
         let line = maybe_line.unwrap();
-        if line.len() == SIZE {
+        if line.len() == N {
             if let Ok(word_vec) = line.chars().map(FChar::try_from).collect():Result<Vec<FChar>,_> {
-                let mut word = [FChar::try_from('a').unwrap(); SIZE];
+                let mut word = [FChar::try_from('a').unwrap(); N];
                 word.as_mut_slice().copy_from_slice(word_vec.as_slice());
                 words.push(word);
             }
         }
     }
-    dbg!(words.len());
+    words
+}
 
-    let mut map:WMap = Default::default();
+fn build_map<const N: usize>(words: &[Word<N>]) -> WMap<N> {
+    let mut map:WMap<N> = Default::default();
 
-    for word in &words {
-        let mut partial_word:[Option<FChar>; SIZE] = [None; SIZE];
-        for i in 0..SIZE {
+    for word in words {
+        let mut partial_word:OWord<N> = [None; N];
+        for i in 0..N {
             partial_word[i] = Some(word[i]);
         }
-        for i in (0..SIZE).rev() {
+        for i in (0..N).rev() {
             let c = partial_word[i].unwrap();
             partial_word[i] = None;
             let set = map.entry(partial_word).or_default();
             *set = set.set(c);
         }
     }
+    map
+}
 
-    dbg!(map.len());
+/// Parses a partial word such as `"aba.a"`, where `.` or `_` is an unfilled letter. Letters past
+/// the end of the string are unfilled.
+fn parse_oword<const N: usize>(s: &str) -> Result<OWord<N>, &'static str> {
+    let mut word:OWord<N> = [None; N];
+    if s.chars().count() > N {
+        return Err("Partial word is longer than the word size");
+    }
+    for (i, c) in s.chars().enumerate() {
+        word[i] = match c {
+            '.' | '_' => None,
+            c => Some(FChar::try_from(c)?),
+        };
+    }
+    Ok(word)
+}
 
-    if false {
-        find_squares(&words, &map);
-        std::process::exit(0);
+/// Word sizes the binary is built for, each its own copy of `run`, so the top is just where the
+/// list stops. `random_partial_word` fills in at least 2 letters and leaves at least 1 empty, so
+/// words need at least 3.
+const SIZES: std::ops::RangeInclusive<usize> = 3..=15;
+
+fn main() {
+    let opt = Opt::from_args();
+    // these don't need the word list
+    match &opt.cmd {
+        Command::Asm(opts) => return assemble(opts, opt.size),
+        Command::Disasm(opts) => return disassemble(opts),
+        Command::Export(opts) => return export(opts),
+        Command::Front(opts) => return print_front(opts),
+        _ => (),
+    }
+    match opt.size {
+        3 => run::<3>(&opt),
+        4 => run::<4>(&opt),
+        5 => run::<5>(&opt),
+        6 => run::<6>(&opt),
+        7 => run::<7>(&opt),
+        8 => run::<8>(&opt),
+        9 => run::<9>(&opt),
+        10 => run::<10>(&opt),
+        11 => run::<11>(&opt),
+        12 => run::<12>(&opt),
+        13 => run::<13>(&opt),
+        14 => run::<14>(&opt),
+        15 => run::<15>(&opt),
+        size => {
+            eprintln!("Word size {} is not supported, must be in {:?}", size, SIZES);
+            std::process::exit(1);
+        }
     }
+}
 
-    let mut rng = rand::thread_rng();
-    #[derive(Debug,Serialize,Deserialize)]
-    struct Genome {
-        instructions: Vec<Gene>,
-        mutation_rate: f64,
-        fitness: f64,
+fn run<const N: usize>(opt: &Opt) {
+    dbg!(N);
+
+    let words = load_words::<N, _>(&opt.words);
+    dbg!(words.len());
+    if words.is_empty() {
+        eprintln!("No words of length {} in {:?}", N, opt.words);
+        std::process::exit(1);
     }
 
-    #[derive(Debug,Serialize,Deserialize)]
-    struct State {
-        round: usize,
-        pool: Vec<Genome>,
+    let map = build_map(&words);
+    dbg!(map.len());
+
+    match &opt.cmd {
+        Command::Evolve(opts) => evolve(opts, opt.seed, &map),
+        Command::Search(opts) => find_squares(opts, &words, &map),
+        Command::Eval(opts) => eval(opts, opt.seed, &map),
+        Command::Optimize(opts) => optimize_genome(opts, &map),
+        Command::Trace(opts) => trace(opts, &map),
+        Command::Baseline(opts) => baseline(opts, &map),
+        Command::Asm(_) | Command::Disasm(_) | Command::Export(_) | Command::Front(_) => unreachable!(),
     }
-    //let mut pool:Vec<Genome> = Vec::with_capacity(GENEPOOL_SIZE);
-    let mut state = State{
-        round: 1,
-        pool: Vec::with_capacity(GENEPOOL_SIZE),
-    };
+}
 
-    for _ in 0..GENEPOOL_SIZE {
-        let mut instructions:Vec<Gene> = Vec::with_capacity(INSTRUCTION_SIZE_INIT);
+/// The genome at `--index` in a checkpoint, or an error and exit if the pool isn't that long
+fn genome_at(state: &State, index: usize) -> &Genome {
+    match state.pool.get(index) {
+        Some(genome) => genome,
+        None => {
+            eprintln!("--index {} is past the end of a pool of {}", index, state.pool.len());
+            std::process::exit(1);
+        }
+    }
+}
 
-        for _ in 0..INSTRUCTION_SIZE_INIT {
-            instructions.push(Gene{order: rng.gen(), ins: SvmInstruction::random(&mut rng)});
+fn assemble(opts: &AsmOpts, size: usize) {
+    let text = std::fs::read_to_string(&opts.input).unwrap();
+    let instructions = match asm::parse_program(&text) {
+        Ok(genes) => genes,
+        Err(e) => {
+            eprintln!("{}: {}", opts.input.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let features = match Features::parse_list(&opts.features) {
+        Ok(features) => features,
+        Err(e) => {
+            eprintln!("--features: {}", e);
+            std::process::exit(1);
         }
-        instructions.sort_unstable_by(|a,b| a.order.total_cmp(&b.order));
-        state.pool.push(Genome{
+    };
+    let svm = match asm::machine(&instructions, features.num_inputs(size), opts) {
+        Ok(svm) => svm,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let state = State{
+        round: 1,
+        size,
+        seed: None,
+        svm,
+        features,
+        islands: 1,
+        pool: vec![Genome{
             instructions,
             mutation_rate: 0.5,
+            point_rates: PointRates::default(),
             fitness: 0.0,
-        });
+            objectives: Objectives::default(),
+        }],
+        front: Vec::new(),
+    };
+    save_state(&opts.output, &state).unwrap();
+    println!("Wrote {:?}", opts.output);
+}
+
+fn disassemble(opts: &DisasmOpts) {
+    let state = load_state(&opts.checkpoint).unwrap();
+    let genome = genome_at(&state, opts.index);
+    println!("# round {}, genome {}, fitness {}", state.round, opts.index, genome.fitness);
+    println!("# machine {}", state.svm);
+    println!("# inputs {}", state.features);
+    let feature_names:Vec<&str> = state.features.iter().map(features::Feature::name).collect();
+    println!(
+        "# reassemble with: fws2 --size {} asm <this file> <checkpoint> {} --features {:?}",
+        state.size,
+        asm::machine_flags(&state.svm),
+        feature_names.join(","),
+    );
+    print!("{}", asm::format_program(&genome.instructions));
+}
+
+fn export(opts: &ExportOpts) {
+    let state = load_state(&opts.checkpoint).unwrap();
+    let genome = genome_at(&state, opts.index);
+    let mut f = io::BufWriter::new(File::create(&opts.output).unwrap());
+    encode::write_program(&mut f, state.size, &state.svm, state.features, &genome.instructions).unwrap();
+    f.into_inner().unwrap().sync_all().unwrap();
+    println!("Wrote {:?}", opts.output);
+}
+
+fn print_front(opts: &FrontOpts) {
+    let state = load_state(&opts.checkpoint).unwrap();
+    let mut front = state.front.clone();
+    // fewest false positives first, so it reads as a trade-off against false negatives
+    front.sort_by(|a,b| state.pool[*a].objectives.0.partial_cmp(&state.pool[*b].objectives.0).unwrap());
+    println!("# round {}, {} genomes on the front", state.round, front.len());
+    for i in front {
+        let genome = &state.pool[i];
+        println!("{:>5}: {}, fitness {:.5}", i, genome.objectives, genome.fitness);
     }
+}
 
-    //let mut round = 1;
-    loop {
-        for g in &mut state.pool {
-            g.fitness = fitness(g.instructions.iter().map(|g| g.ins), &map, &mut rng, NUM_TRIALS);
-        }
-        state.pool.sort_by(|a,b| a.fitness.partial_cmp(&b.fitness).unwrap());
-        if state.round % 16 == 0 {
-            let filename = format!("round{}.json.gz",state.round);
-            println!("Wrote {:?}", filename);
-            let f = std::fs::File::create(&filename).unwrap();
-            let mut encoder = Encoder::new(f).unwrap();
-            serde_json::to_writer(&mut encoder, &state).unwrap();
-            encoder.finish().unwrap().0.sync_all().unwrap();
-        }
-        let first = state.pool.first().unwrap();
-        for ins in &first.instructions {
-            println!("{:.5}: {}", ins.order, ins.ins)
+fn eval<const N: usize>(opts: &EvalOpts, seed: Option<u64>, map: &WMap<N>) {
+    let state = load_state(&opts.checkpoint).unwrap();
+    assert_eq!(state.size, N, "Checkpoint was evolved with a different word size");
+    let genome = genome_at(&state, opts.index);
+    let program = genome.compile(&state.svm);
+    let inputs = InputEncoder::new(state.features, map);
+    for ins in &genome.instructions {
+        println!("{:.5}: {}", ins.order, ins.ins)
+    }
+    let seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+    println!("Seed {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (fitness, objectives) = score(&program, map, &inputs, &mut rng, &opts.fitness);
+    println!(
+        "Round {}, genome {}: {} instructions, recorded fitness {:.5}, fitness {:.5}",
+        state.round,
+        opts.index,
+        genome.instructions.len(),
+        genome.fitness,
+        fitness,
+    );
+    println!("{}", objectives);
+    let exact = exact_score(map, &opts.fitness, |word| run_program(&program, &inputs, *word));
+    println!("{}", exact);
+    println!("Exact fitness {:.5}", exact.fitness(genome.instructions.len(), &opts.fitness));
+    println!("{}", exact::certify(map, |word| run_program(&program, &inputs, *word)));
+}
+
+fn optimize_genome<const N: usize>(opts: &OptimizeOpts, map: &WMap<N>) {
+    let state = load_state(&opts.checkpoint).unwrap();
+    assert_eq!(state.size, N, "Checkpoint was evolved with a different word size");
+    let genome = genome_at(&state, opts.index);
+    let program:Vec<SvmInstruction> = genome.instructions.iter().map(|g| g.ins).collect();
+    let genes:Vec<Gene> = optimize::optimize(&program, &state.svm).into_iter()
+        .map(|(i, ins)| Gene{order: genome.instructions[i].order, ins})
+        .collect();
+
+    let before = CompiledProgram::compile(program.iter().copied(), &state.svm);
+    let after = CompiledProgram::compile(genes.iter().map(|g| g.ins), &state.svm);
+    let inputs = InputEncoder::new(state.features, map);
+    let mismatches = map.keys()
+        .filter(|word| run_program(&before, &inputs, **word) != run_program(&after, &inputs, **word))
+        .count();
+    if mismatches != 0 {
+        eprintln!("Optimized program differs on {} of {} partial words, not using it", mismatches, map.len());
+        std::process::exit(1);
+    }
+
+    println!("# {} instructions optimized to {}, same output on all {} partial words", program.len(), genes.len(), map.len());
+    print!("{}", asm::format_program(&genes));
+    if let Some(output) = &opts.output {
+        let mut f = io::BufWriter::new(File::create(output).unwrap());
+        encode::write_program(&mut f, N, &state.svm, state.features, &genes).unwrap();
+        f.into_inner().unwrap().sync_all().unwrap();
+        println!("Wrote {:?}", output);
+    }
+}
+
+fn letters(value: u32) -> String {
+    CharSet::from(value).into_iter().map(char::from).collect()
+}
+
+fn trace<const N: usize>(opts: &TraceOpts, map: &WMap<N>) {
+    let state = load_state(&opts.checkpoint).unwrap();
+    assert_eq!(state.size, N, "Checkpoint was evolved with a different word size");
+    let word:OWord<N> = match parse_oword(&opts.word) {
+        Ok(word) => word,
+        Err(e) => {
+            eprintln!("{:?}: {}", opts.word, e);
+            std::process::exit(1);
         }
-        println!("First mutation rate {}", first.mutation_rate);
-        fitness_single(
-            first.instructions.iter().map(|a| a.ins),
-            &map, 
-            [
-                Some(FChar::try_from('a').unwrap()),
-                Some(FChar::try_from('b').unwrap()),
-                Some(FChar::try_from('a').unwrap()),
-                Some(FChar::try_from('c').unwrap()),
-                Some(FChar::try_from('a').unwrap()),
-                None,
-                None,
-                None,
-                None,
-                None,
-            ],
-            true,
-        );
-        fitness_single(
-            first.instructions.iter().map(|a| a.ins),
-            &map, 
-            [
-                Some(FChar::try_from('a').unwrap()),
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-            ],
-            true,
-        );
+    };
+    let program:Vec<SvmInstruction> = genome_at(&state, opts.index).instructions.iter().map(|g| g.ins).collect();
+    let mut svm = SvmState::new(&program, &state.svm);
+    InputEncoder::new(state.features, map).encode(&word, &mut svm.memory_mut()[state.svm.inputs()]);
+    svm.set_max_steps(opts.max_steps);
+    svm.record_trace();
+    let result = loop {
+        match svm.step() {
+            StepResult::Continue => (),
+            res => break res,
+        }
+    };
+
+    for t in svm.trace() {
+        let ins = t.ins.to_string();
+        if t.ins.is_control() {
+            let went = if t.next_pc == t.pc + 1 { String::from("no jump") } else { format!("to {}", t.next_pc) };
+            println!("{:>5} {:<14} r{:<2} {:#010x} {}", t.pc, ins, t.ins.dest, t.before, went);
+        } else {
+            println!("{:>5} {:<14} r{:<2} {:#010x} -> {:#010x}", t.pc, ins, t.ins.dest, t.before, t.after);
+        }
+    }
+    match result {
+        StepResult::OutOfSteps => println!("Stopped after {} steps without finishing", svm.steps()),
+        _ => println!("Finished after {} steps", svm.steps()),
+    }
+
+    let guess = svm.memory()[usize::from(state.svm.output)];
+    let real:u32 = map.get(&word).copied().unwrap_or_default().into();
+    let (false_positives, false_negatives) = guess_errors(guess, real);
+    println!("Guess {:#010x} {:?}", guess, letters(guess));
+    println!("Real  {:#010x} {:?}", real, letters(real));
+    println!("{} false positives, {} false negatives", false_positives, false_negatives);
+}
+
+fn baseline<const N: usize>(opts: &BaselineOpts, map: &WMap<N>) {
+    let table = LetterTable::build(map, &opts.fitness);
+    println!("# table by number of filled letters and previous letter, {} entries", table.entries());
+    if opts.show {
+        print!("{}", table);
+    }
+    let exact = exact_score(map, &opts.fitness, |word| table.predict(word));
+    println!("{}", exact);
+    println!("Exact fitness {:.5}", exact.fitness(0, &opts.fitness));
+
+    let tree = DecisionTree::build(map, opts.depth, &opts.fitness);
+    println!("# decision tree of depth {}, {} leaves", opts.depth, tree.leaves());
+    if opts.show {
+        print!("{}", tree);
+    }
+    let exact = exact_score(map, &opts.fitness, |word| tree.predict(word));
+    println!("{}", exact);
+    println!("Exact fitness {:.5}", exact.fitness(0, &opts.fitness));
+}
+
+fn evolve<const N: usize>(opts: &EvolveOpts, seed: Option<u64>, map: &WMap<N>) {
+    rayon::ThreadPoolBuilder::new().num_threads(opts.threads).build_global().unwrap();
+    //let mut pool:Vec<Genome> = Vec::with_capacity(opts.pool_size);
+    let mut state;
+    // A checkpoint is written after the pool has been scored and sorted, so a resumed run picks up
+    // right after that point instead of re-scoring the pool.
+    let mut skip_fitness;
+    if let Some(filename) = &opts.resume {
+        state = load_state(filename).unwrap();
+        assert_eq!(state.size, N, "Checkpoint was evolved with a different word size");
+        println!("Resumed from {:?} at round {}", filename, state.round);
+        // an explicit --seed wins, so a run can be branched off from a checkpoint
+        state.seed = seed.or(state.seed);
+        if opts.registers.is_some() || opts.output_register.is_some() || opts.first_input.is_some() || opts.features.is_some() || opts.islands.is_some() {
+            eprintln!("--registers, --output-register, --first-input, --features and --islands can't change a resumed run");
+            std::process::exit(1);
+        }
+        skip_fitness = true;
+    } else {
+        let features = match Features::parse_list(opts.features.as_deref().unwrap_or_default()) {
+            Ok(features) => features,
+            Err(e) => {
+                eprintln!("--features: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let mut svm = SvmConfig::original(features.num_inputs(N));
+        svm.num_registers = opts.registers.unwrap_or(svm.num_registers);
+        svm.output = opts.output_register.unwrap_or(svm.output);
+        svm.first_input = opts.first_input.unwrap_or(svm.first_input);
+        if let Err(e) = svm.validate() {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        state = State{
+            round: 1,
+            size: N,
+            seed,
+            svm,
+            features,
+            islands: opts.islands.unwrap_or(1),
+            pool: Vec::new(),
+            front: Vec::new(),
+        };
+        skip_fitness = false;
+    }
+    let seed = *state.seed.get_or_insert_with(|| rand::thread_rng().gen());
+    println!("Seed {}", seed);
+    // same as the seed, these add to or override what a resumed checkpoint had
+    if let Some(extensions) = &opts.extensions {
+        if let Err(e) = state.svm.instruction_set.enable_extensions(extensions) {
+            eprintln!("--extensions: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(step_budget) = opts.step_budget {
+        state.svm.instruction_set.step_budget = step_budget;
+    }
+    let svm = state.svm;
+    println!("Machine {}", svm);
+    println!("Inputs {}", state.features);
+    let inputs = InputEncoder::new(state.features, map);
+    let breeding = Breeding{
+        selection: selection::from_opts(opts),
+        crossover: crossover::from_opts(opts),
+        niching: niching::from_opts(opts),
+    };
+    println!("Selection {:?}", breeding.selection);
+    println!("Crossover {:?}", breeding.crossover);
+    println!("Niching {:?}, distance {:?}", breeding.niching, opts.distance);
+    if opts.pareto || opts.zero_false_negatives {
+        if let Niching::Sharing{..} = breeding.niching {
+            eprintln!("--niching sharing ranks by fitness, so it can't be used with --pareto or --zero-false-negatives");
+            std::process::exit(1);
+        }
+        if opts.selection == selection::SelectionTy::Proportional {
+            eprintln!("--selection proportional weighs by fitness, so it can't be used with --pareto or --zero-false-negatives");
+            std::process::exit(1);
+        }
+    }
+    let ranking = pareto::from_opts(opts);
+    match ranking {
+        pareto::Ranking::Fitness => (),
+        pareto::Ranking::Pareto => println!("Ranking by Pareto front"),
+        pareto::Ranking::Lexicographic => println!("Ranking by false negatives, then false positives, then length"),
+    }
+    if state.islands > 1 {
         println!(
-            "Round {}, best/med/worst {:.5}/{:.5}/{:.5}",
-            state.round,
-            state.pool.first().unwrap().fitness,
-            state.pool[state.pool.len()/2].fitness,
-            state.pool.last().unwrap().fitness,
+            "Islands {}, migrating {} {:?} every {} rounds",
+            state.islands, opts.migration_size, opts.migration, opts.migration_interval,
         );
-        // for _ in 0..((pool.len()/4)*3) {
-        //     pool.pop();
-        // }
-        let prev_len = state.pool.len() as f64;
-        let mut i = 0;
-        #[allow(clippy::eval_order_dependence)]
-        state.pool.retain(|_| (rng.gen::<f64>() > ((i as f64)/prev_len), i += 1).0);
-        let parents_end = state.pool.len();
-        dbg!(parents_end);
-        for _ in parents_end..GENEPOOL_SIZE {
-            let parents = (
-                &state.pool[rng.gen_range(0..parents_end)],
-                &state.pool[rng.gen_range(0..parents_end)],
-            );
-            let mut genes = Vec::new();
-            for mut b in diff(parents.0.instructions.iter().copied(), parents.1.instructions.iter().copied()) {
-                match b.ty {
-                    DiffTy::Both => genes.append(&mut b.block),
-                    _ => if rng.gen():bool { genes.append(&mut b.block) },
-                }
+    }
+
+    // Sampled once for the whole run, so validation scores can be compared between rounds.
+    let validation:Vec<OWord<N>> = {
+        let mut rng = round_rng(seed, 0, STREAM_VALIDATION);
+        (0..opts.validation_trials).map(|_| random_partial_word(map, &mut rng)).collect()
+    };
+    // also sampled once, so a genome's outputs mean the same thing every round
+    let behavior:Vec<OWord<N>> = if opts.distance == DistanceTy::Behavioral {
+        let mut rng = round_rng(seed, 0, STREAM_BEHAVIOR);
+        (0..opts.behavior_trials).map(|_| random_partial_word(map, &mut rng)).collect()
+    } else {
+        Vec::new()
+    };
+    let profile = |g: &Genome| match opts.distance {
+        DistanceTy::Genotypic => Profile::Genes(g.instructions.clone()),
+        DistanceTy::Behavioral => Profile::Outputs(outputs_on(&g.compile(&svm), &inputs, &behavior)),
+    };
+
+    if state.pool.is_empty() {
+        let mut rng = round_rng(seed, 0, 0);
+
+        for _ in 0..opts.pool_size * state.islands {
+            let mut instructions:Vec<Gene> = Vec::with_capacity(opts.instructions_init);
+
+            for _ in 0..opts.instructions_init {
+                instructions.push(Gene{order: rng.gen(), ins: SvmInstruction::random(&mut rng, &svm)});
             }
-            let mut mutation_rates = [parents.0.mutation_rate, parents.1.mutation_rate];
-            mutation_rates.sort_unstable_by(|a,b| a.partial_cmp(&b).unwrap());
-            let child_mutation_rate;
-            if mutation_rates[0].eq(&mutation_rates[1]) {
-                child_mutation_rate = mutation_rates[0];
+            instructions.sort_unstable_by(|a,b| a.order.total_cmp(&b.order));
+            state.pool.push(Genome{
+                instructions,
+                mutation_rate: 0.5,
+                point_rates: PointRates::default(),
+                fitness: 0.0,
+                objectives: Objectives::default(),
+            });
+        }
+    }
+    // fresh genomes and ones from before point mutations were on start them at the same rates
+    if opts.point_mutations {
+        for g in state.pool.iter_mut().filter(|g| g.point_rates.is_off()) {
+            g.point_rates = PointRates::initial();
+        }
+    }
+
+    //let mut round = 1;
+    loop {
+        if !skip_fitness {
+            // Each genome gets its own rng, so the scores don't depend on how the pool happens to
+            // be split across threads.
+            let round = state.round;
+            if opts.shared_trials {
+                let mut rng = round_rng(seed, round, STREAM_SHARED_TRIALS);
+                let trials:Vec<OWord<N>> = (0..opts.fitness.trials).map(|_| random_partial_word(map, &mut rng)).collect();
+                state.pool.par_iter_mut().for_each(|g| {
+                    (g.fitness, g.objectives) = score_on(&g.compile(&svm), map, &inputs, &trials, &opts.fitness);
+                });
             } else {
-                child_mutation_rate = rng.gen_range(mutation_rates[0]..mutation_rates[1]);
+                state.pool.par_iter_mut().enumerate().for_each(|(i, g)| {
+                    let mut rng = round_rng(seed, round, i as u64 + 1);
+                    (g.fitness, g.objectives) = score(&g.compile(&svm), map, &inputs, &mut rng, &opts.fitness);
+                });
             }
-            let mut child = Genome{
-                instructions: genes,
-                mutation_rate: child_mutation_rate,
-                fitness: -1.0,
-            };
-            child.mutation_rate += (rng.gen::<f64>() - 0.5)*child.mutation_rate*0.1;
-            if child.mutation_rate > 0.9 {
-                child.mutation_rate = 0.9;
+            let len = state.island_len();
+            for island in state.pool.chunks_mut(len) {
+                ranking.sort(island);
             }
-            if child.mutation_rate < 0.01 {
-                child.mutation_rate = 0.01;
+            let objectives:Vec<Objectives> = state.pool.iter().map(|g| g.objectives).collect();
+            state.front = pareto::front(&objectives);
+            if opts.checkpoint_interval != 0 && state.round % opts.checkpoint_interval == 0 {
+                let filename = format!("round{}.fws",state.round);
+                println!("Wrote {:?}", filename);
+                save_state(&filename, &state).unwrap();
             }
-            while rng.gen::<f64>() < child.mutation_rate {
-                if rng.gen::<bool>() && child.instructions.len() < INSTRUCTION_SIZE_MAX {
-                    //add a random gene
-                    child.instructions.push(Gene{order: rng.gen(), ins: SvmInstruction::random(&mut rng)});
-                    child.instructions.sort();
-                } else {
-                    //remove a random gene
-                    if !child.instructions.is_empty() {
-                        child.instructions.remove(rng.gen_range(0..child.instructions.len()));
-                    }
-                }
+        }
+        skip_fitness = false;
+        let first = ranking.best(&state.pool);
+        for ins in &first.instructions {
+            println!("{:.5}: {}", ins.order, ins.ins)
+        }
+        println!("First mutation rate {}", first.mutation_rate);
+        if opts.point_mutations {
+            println!("First point mutation rates {}", first.point_rates);
+        }
+        let first_program = first.compile(&svm);
+        for sample in &["abaca", "a"] {
+            fitness_single(
+                &first_program,
+                map,
+                &inputs,
+                parse_oword(&sample[..sample.len().min(N-1)]).unwrap(),
+                &opts.fitness,
+                true,
+            );
+        }
+        let len = state.island_len();
+        let (best, median, worst) = spread(&state.pool);
+        println!("Round {}, best/med/worst {:.5}/{:.5}/{:.5}", state.round, best, median, worst);
+        if state.islands > 1 {
+            for (i, island) in state.pool.chunks(len).enumerate() {
+                let (best, median, worst) = spread(island);
+                println!("Round {}, island {} best/med/worst {:.5}/{:.5}/{:.5}", state.round, i, best, median, worst);
+            }
+        }
+        if opts.pareto {
+            let front:Vec<Objectives> = state.front.iter().map(|i| state.pool[*i].objectives).collect();
+            let fewest = |o: usize| front.iter().map(|f| f.0[o]).fold(f64::INFINITY, f64::min);
+            println!(
+                "Round {}, front of {}, fewest false positives/false negatives/instructions {:.4}/{:.4}/{}",
+                state.round,
+                front.len(),
+                fewest(0),
+                fewest(1),
+                fewest(2),
+            );
+        }
+        if opts.zero_false_negatives {
+            let feasible:Vec<&Objectives> = state.pool.iter().map(|g| &g.objectives).filter(|o| o.feasible()).collect();
+            match feasible.iter().min_by(|a,b| [a.0[0], a.0[2]].partial_cmp(&[b.0[0], b.0[2]]).unwrap()) {
+                Some(best) => println!(
+                    "Round {}, {} with no false negatives, best false positives {:.4}, instructions {}",
+                    state.round,
+                    feasible.len(),
+                    best.0[0],
+                    best.0[2],
+                ),
+                None => println!("Round {}, none with no false negatives", state.round),
             }
-            state.pool.push(child);
         }
+        // evenly spread over the pool, so every island gets a say
+        let step = state.pool.len().div_ceil(niching::DIVERSITY_SAMPLE).max(1);
+        let profiles:Vec<Profile> = state.pool.par_iter().step_by(step).map(profile).collect();
+        println!("Round {}, diversity {:.5}", state.round, niching::diversity(&profiles));
+        if !validation.is_empty() {
+            println!(
+                "Round {}, best validation {:.5}",
+                state.round,
+                fitness_on(&first_program, map, &inputs, &validation, &opts.fitness),
+            );
+        }
+        if state.islands > 1 && opts.migration_interval != 0 && state.round % opts.migration_interval == 0 {
+            let mut rng = round_rng(seed, state.round, STREAM_MIGRATION);
+            islands::migrate(&mut state.pool, state.islands, opts.migration_size, opts.migration, ranking, &mut rng);
+        }
+        // for _ in 0..((pool.len()/4)*3) {
+        //     pool.pop();
+        // }
+        let mut pool = Vec::with_capacity(opts.pool_size * state.islands);
+        let mut old_pool = std::mem::take(&mut state.pool).into_iter();
+        for i in 0..state.islands {
+            let mut rng = round_rng(seed, state.round, island_stream(i));
+            let mut island:Vec<Genome> = old_pool.by_ref().take(len).collect();
+            breed(&mut island, opts, &svm, &breeding, &profile, &mut rng);
+            pool.append(&mut island);
+        }
+        state.pool = pool;
 
         state.round += 1;
     }
 }
 
-fn find_squares(words: &[Word], map: &WMap) {
-    let mut square:[[Option<FChar>; SIZE]; SIZE] = [[None; SIZE]; SIZE];
+/// Best, median and worst fitness, however the genomes are sorted
+fn spread(genomes: &[Genome]) -> (f64, f64, f64) {
+    let mut fitness:Vec<f64> = genomes.iter().map(|g| g.fitness).collect();
+    fitness.sort_by(|a,b| a.partial_cmp(b).unwrap());
+    (fitness[0], fitness[fitness.len()/2], fitness[fitness.len()-1])
+}
+
+/// What evolve picked from the options for how each island is bred
+struct Breeding {
+    selection: Box<dyn selection::Selection>,
+    crossover: Box<dyn crossover::Crossover>,
+    niching: Niching,
+}
 
-    let mut num_squares = 0;
-    let start = std::time::Instant::now();
-    for word in &words[0..10] {
-        for i in 0..SIZE {
-            square[0][i] = Some(word[i]);
+/// Runs selection on one island, sorted best first, and refills it with children of the survivors.
+fn breed(
+    pool: &mut Vec<Genome>,
+    opts: &EvolveOpts,
+    svm: &SvmConfig,
+    breeding: &Breeding,
+    profile: &(dyn Fn(&Genome) -> Profile + Sync),
+    rng: &mut StdRng,
+) {
+    if let Niching::Sharing{radius} = breeding.niching {
+        let profiles:Vec<Profile> = pool.par_iter().map(profile).collect();
+        let mut fitness:Vec<f64> = pool.iter().map(|g| g.fitness).collect();
+        niching::share(&mut fitness, &profiles, radius);
+        // every genome is scored again next round, so the raw fitness isn't needed after this
+        for (g, f) in pool.iter_mut().zip(fitness) {
+            g.fitness = f;
         }
-        recurse(square,0,1,&map,&mut num_squares);
+        pool.sort_by(|a,b| a.fitness.partial_cmp(&b.fitness).unwrap());
     }
-    dbg!(start.elapsed());
-    dbg!(&num_squares);
-}
-
-fn recurse(s:Square,col:usize,row:usize,map:&WMap,count:&mut u64) {
-    if row == SIZE {
-        for i in 0..SIZE {
-            for j in 0..SIZE {
-                print!("{} ", s[i][j].unwrap().into():char);
+    let fitness:Vec<f64> = pool.iter().map(|g| g.fitness).collect();
+    let survivors = breeding.selection.survivors(&fitness, rng);
+    // more survivors than fit only happens after resuming with a smaller --pool-size
+    let parent_ids:Vec<usize> = if let Niching::Crowding{..} = breeding.niching {
+        // the survivors are only parents, and stay where they are until a child replaces them
+        pool.truncate(opts.pool_size);
+        (0..pool.len()).filter(|i| survivors[*i]).collect()
+    } else {
+        let mut survivors = survivors.into_iter();
+        pool.retain(|_| survivors.next().unwrap());
+        pool.truncate(opts.pool_size);
+        (0..pool.len()).collect()
+    };
+    let parents_end = parent_ids.len();
+    dbg!(parents_end);
+    let mut children = Vec::with_capacity(opts.pool_size.saturating_sub(parents_end));
+    for _ in parents_end..opts.pool_size {
+        let parents = (
+            &pool[parent_ids[rng.gen_range(0..parents_end)]],
+            &pool[parent_ids[rng.gen_range(0..parents_end)]],
+        );
+        let genes = breeding.crossover.cross(&parents.0.instructions, &parents.1.instructions, rng);
+        let mut child = Genome{
+            instructions: genes,
+            mutation_rate: mutation::inherit_rate(parents.0.mutation_rate, parents.1.mutation_rate, rng),
+            point_rates: PointRates::default(),
+            fitness: -1.0,
+            objectives: Objectives::default(),
+        };
+        while rng.gen::<f64>() < child.mutation_rate {
+            if rng.gen::<bool>() && child.instructions.len() < opts.instructions_max {
+                //add a random gene
+                child.instructions.push(Gene{order: rng.gen(), ins: SvmInstruction::random(rng, svm)});
+                child.instructions.sort();
+            } else {
+                //remove a random gene
+                if !child.instructions.is_empty() {
+                    child.instructions.remove(rng.gen_range(0..child.instructions.len()));
+                }
             }
-            println!();
         }
-        println!();
-        // sleep(Duration::from_millis(1000));
-        *count += 1;
+        if opts.point_mutations {
+            child.point_rates = PointRates::inherit(&parents.0.point_rates, &parents.1.point_rates, rng);
+            child.point_rates.mutate(&mut child.instructions, svm, opts.instructions_max, rng);
+        }
+        children.push(child);
     }
-    let mut col_key:OWord = [None; SIZE];
-    let mut row_key:OWord = [None; SIZE];
-    row_key.copy_from_slice(&s[row]);
-    // for i in 0..col {
-    //     row_key[i] = s[row][i];
-    // }
-    for i in 0..row {
-        col_key[i] = s[i][col];
-    }
-    //dbg!(col_key, row_key);
-    let col_set = map.get(&col_key).copied().unwrap_or_default();
-    let row_set = map.get(&row_key).copied().unwrap_or_default();
-    let and_set = col_set & row_set;
-    let new_col = (col+1) % SIZE;
-    let new_row = if col == SIZE-1 {
-        row + 1
-    } else { row };
-    //dbg!(col_set, row_set, and_set);
-    //confirm();
-    for c in and_set.into_iter() {
-        //if col == 0 && row > 0 && s[col][row].unwrap() > c { continue; }
-        let mut new_s = s;
-        new_s[row][col] = Some(c);
-        recurse(new_s,new_col,new_row,map,count);
+    match breeding.niching {
+        Niching::Crowding{factor} if pool.len() >= 2 => {
+            let mut profiles:Vec<Profile> = pool.par_iter().map(profile).collect();
+            for child in children {
+                let child_profile = profile(&child);
+                if pool.len() < opts.pool_size {
+                    pool.push(child);
+                    profiles.push(child_profile);
+                } else {
+                    let i = niching::crowded(&child_profile, &profiles, factor, rng);
+                    pool[i] = child;
+                    profiles[i] = child_profile;
+                }
+            }
+        }
+        _ => pool.append(&mut children),
     }
 }
 
+fn find_squares<const N: usize>(opts: &SearchOpts, words: &[Word<N>], map: &WMap<N>) {
+    let first_rows = &words[0..opts.first_rows.min(words.len())];
+    // the genome is read first, so a bad checkpoint or --index fails before any searching
+    let genome = opts.genome.as_ref().map(|path| {
+        let state = load_state(path).unwrap();
+        assert_eq!(state.size, N, "Checkpoint was evolved with a different word size");
+        let program = genome_at(&state, opts.index).compile(&state.svm);
+        (program, InputEncoder::new(state.features, map))
+    });
+    let lookup = |word: &OWord<N>| map.get(word).copied().unwrap_or_default();
+    let baseline = search::search(first_rows, map, &lookup, false);
+    let (program, inputs) = match &genome {
+        Some(genome) => genome,
+        None => {
+            baseline.squares.iter().for_each(search::print_square);
+            println!("WMap: {}", baseline);
+            return;
+        }
+    };
+
+    let guess = |word: &OWord<N>| CharSet::from(run_program(program, inputs, *word) & u32::from(CharSet::full()));
+    let filtered = match opts.filter {
+        search::Filter::Intersect => search::search(first_rows, map, &|word| lookup(word) & guess(word), false),
+        search::Filter::Substitute => search::search(first_rows, map, &guess, true),
+    };
+    filtered.squares.iter().for_each(search::print_square);
+    let found:fnv::FnvHashSet<&Square<N>> = filtered.squares.iter().collect();
+    let missed = baseline.squares.iter().filter(|s| !found.contains(s)).count();
+    println!("WMap: {}", baseline);
+    println!("Genome {} ({:?}): {}, missing {} of the WMap's squares", opts.index, opts.filter, filtered, missed);
+    println!(
+        "{:.2}% of the nodes, {:.2}x the time",
+        filtered.nodes as f64 * 100.0 / baseline.nodes as f64,
+        filtered.elapsed.as_secs_f64() / baseline.elapsed.as_secs_f64(),
+    );
+}
+
 #[allow(dead_code)]
-fn count(
+fn count<const N: usize>(
     levels:usize,
-    thing:OWord,
+    thing:OWord<N>,
     index:usize,
-    map:&WMap
+    map:&WMap<N>
 ) -> usize {
     let set = map.get(&thing).copied().unwrap_or_default();
     assert!(thing[index].is_none());
@@ -0,0 +1,219 @@
+use std::fmt;
+use rand::Rng;
+use rand::rngs::StdRng;
+use serde_derive::{Serialize,Deserialize};
+
+use super::genetics::Gene;
+use super::svm::{SvmConfig, SvmInstructionTy};
+
+/// A child's rate: somewhere between its parents', then nudged up or down by up to 5%, so rates
+/// that do well spread through the pool along with the genes.
+pub fn inherit_rate(a: f64, b: f64, rng: &mut StdRng) -> f64 {
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+    let mut rate = if low < high { rng.gen_range(low..high) } else { low };
+    rate += (rng.gen::<f64>() - 0.5)*rate*0.1;
+    rate.clamp(0.01, 0.9)
+}
+
+/// Keeps an order in 0..1, the range asm accepts and new genes are drawn from
+fn clamp_order(order: f64) -> f64 {
+    order.clamp(0.0, 1.0 - f64::EPSILON)
+}
+
+/// Changes to a genome that leave its length alone, or in the case of `Duplicate` grow it by
+/// copying rather than by making up new genes.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum PointMutation {
+    /// Gives a gene a random instruction type, keeping its operands where they still make sense
+    Opcode,
+    Dest,
+    Src,
+    /// Moves a gene by up to 0.05 in order, stopping at either end of 0..1
+    Order,
+    /// Copies up to 4 genes in a row to a random spot
+    Duplicate,
+    /// Swaps two genes' instructions, leaving the orders where they were
+    Swap,
+}
+
+impl PointMutation {
+    pub const ALL: [PointMutation; 6] = [
+        PointMutation::Opcode,
+        PointMutation::Dest,
+        PointMutation::Src,
+        PointMutation::Order,
+        PointMutation::Duplicate,
+        PointMutation::Swap,
+    ];
+
+    /// `genes` must be sorted, and is left sorted.
+    pub fn apply(self, genes: &mut Vec<Gene>, config: &SvmConfig, max_len: usize, rng: &mut StdRng) {
+        if genes.is_empty() {
+            return;
+        }
+        let i = rng.gen_range(0..genes.len());
+        let ins = &mut genes[i].ins;
+        match self {
+            PointMutation::Opcode => {
+                let ty = SvmInstructionTy::random(rng, &config.instruction_set);
+                if ty.src_is_register() != ins.ty.src_is_register() {
+                    ins.src = config.random_src(ty, rng);
+                }
+                ins.ty = ty;
+            }
+            PointMutation::Dest => ins.dest = config.random_register(rng),
+            PointMutation::Src => ins.src = config.random_src(ins.ty, rng),
+            PointMutation::Order => {
+                genes[i].order = clamp_order(genes[i].order + (rng.gen::<f64>() - 0.5) * 0.1);
+                genes.sort();
+            }
+            PointMutation::Duplicate => {
+                let len = rng.gen_range(1..=4.min(genes.len() - i));
+                if genes.len() + len > max_len {
+                    return;
+                }
+                // spread the copies out between two neighbouring genes
+                let at = rng.gen_range(0..=genes.len());
+                let low = if at == 0 { 0.0 } else { genes[at-1].order };
+                let high = if at == genes.len() { 1.0 } else { genes[at].order };
+                let copies:Vec<Gene> = genes[i..i+len].iter().enumerate().map(|(n, g)| Gene{
+                    order: clamp_order(low + (high - low) * (n + 1) as f64 / (len + 1) as f64),
+                    ins: g.ins,
+                }).collect();
+                genes.extend(copies);
+                genes.sort();
+            }
+            PointMutation::Swap => {
+                let j = rng.gen_range(0..genes.len());
+                let ins = genes[i].ins;
+                genes[i].ins = genes[j].ins;
+                genes[j].ins = ins;
+                genes.sort();
+            }
+        }
+    }
+}
+
+/// Rate of each of `PointMutation::ALL`, in the same order. All 0 means point mutations are off.
+#[derive(Debug,Clone,Copy,PartialEq,Default,Serialize,Deserialize)]
+pub struct PointRates(pub [f64; 6]);
+
+impl PointRates {
+    /// What genomes start with when point mutations are turned on
+    pub fn initial() -> Self {
+        PointRates([0.1; 6])
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.0.iter().all(|r| *r == 0.0)
+    }
+
+    pub fn inherit(a: &PointRates, b: &PointRates, rng: &mut StdRng) -> Self {
+        let mut res = PointRates::default();
+        for (r, (a, b)) in res.0.iter_mut().zip(a.0.iter().zip(&b.0)) {
+            *r = inherit_rate(*a, *b, rng);
+        }
+        res
+    }
+
+    /// Applies each point mutation for as long as a random draw stays under its rate, the same way
+    /// `mutation_rate` does adding and removing genes.
+    pub fn mutate(&self, genes: &mut Vec<Gene>, config: &SvmConfig, max_len: usize, rng: &mut StdRng) {
+        for (mutation, rate) in PointMutation::ALL.iter().zip(&self.0) {
+            while rng.gen::<f64>() < *rate {
+                mutation.apply(genes, config, max_len, rng);
+            }
+        }
+    }
+}
+
+impl fmt::Display for PointRates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (mutation, rate)) in PointMutation::ALL.iter().zip(&self.0).enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:?} {:.4}", mutation, rate)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod mutation_test {
+    use super::*;
+    use rand::SeedableRng;
+    use crate::svm::{InstructionSet, SvmInstruction};
+
+    fn config() -> SvmConfig {
+        SvmConfig{ num_registers: 6, instruction_set: InstructionSet::all(), ..SvmConfig::original(3) }
+    }
+
+    fn random_genes(rng: &mut StdRng, len: usize) -> Vec<Gene> {
+        let mut genes:Vec<Gene> = (0..len).map(|_| Gene{order: rng.gen(), ins: SvmInstruction::random(rng, &config())}).collect();
+        genes.sort();
+        genes
+    }
+
+    #[test]
+    fn inherit_rate_stays_between_parents() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let rate = inherit_rate(0.2, 0.4, &mut rng);
+            assert!((0.19..=0.42).contains(&rate), "{}", rate);
+        }
+        assert_eq!(inherit_rate(5.0, 5.0, &mut rng), 0.9);
+        assert_eq!(inherit_rate(0.0, 0.0, &mut rng), 0.01);
+    }
+
+    #[test]
+    fn mutations_keep_genes_valid() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for mutation in PointMutation::ALL {
+            for _ in 0..200 {
+                let len = rng.gen_range(0..10);
+                let mut genes = random_genes(&mut rng, len);
+                let before = genes.clone();
+                mutation.apply(&mut genes, &config(), 12, &mut rng);
+                assert!(genes.windows(2).all(|w| w[0] <= w[1]), "{:?} left genes unsorted", mutation);
+                assert!(config().check_program(genes.iter().map(|g| g.ins)).is_ok(), "{:?}", mutation);
+                assert!(genes.len() <= 12.max(before.len()));
+                assert!(genes.iter().all(|g| (0.0..1.0).contains(&g.order)), "{:?} moved an order out of 0..1", mutation);
+                match mutation {
+                    PointMutation::Duplicate => assert!(genes.len() >= before.len()),
+                    _ => assert_eq!(genes.len(), before.len()),
+                }
+                if mutation == PointMutation::Swap || mutation == PointMutation::Order {
+                    let mut a:Vec<_> = genes.iter().map(|g| g.ins).collect();
+                    let mut b:Vec<_> = before.iter().map(|g| g.ins).collect();
+                    a.sort();
+                    b.sort();
+                    assert_eq!(a, b, "{:?} changed instructions", mutation);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn duplicate_copies_a_run() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let before = random_genes(&mut rng, 8);
+        let mut genes = before.clone();
+        while genes.len() == before.len() {
+            PointMutation::Duplicate.apply(&mut genes, &config(), 100, &mut rng);
+        }
+        let added:Vec<_> = genes.iter().filter(|g| !before.contains(g)).map(|g| g.ins).collect();
+        let copied = before.windows(added.len()).any(|w| w.iter().map(|g| g.ins).eq(added.iter().copied()));
+        assert!(copied, "{:?} isn't a run of {:?}", added, before);
+    }
+
+    #[test]
+    fn off_does_nothing() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let genes = random_genes(&mut rng, 10);
+        let mut mutated = genes.clone();
+        PointRates::default().mutate(&mut mutated, &config(), 100, &mut rng);
+        assert_eq!(mutated, genes);
+        assert!(PointRates::default().is_off() && !PointRates::initial().is_off());
+    }
+}
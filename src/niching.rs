@@ -0,0 +1,211 @@
+use std::str::FromStr;
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use super::cli::EvolveOpts;
+use super::genetics::{diff_sizes, Gene};
+
+/// Bits of a guess that are letters
+const LETTER_BITS:u32 = ((1 << 27) - 1) & !1;
+
+/// Share of the genes in either genome that only one of them has: 0 for the same genes, 1 for
+/// none in common.
+pub fn genotypic_distance(a: &[Gene], b: &[Gene]) -> f64 {
+    let (only_a, only_b, same) = diff_sizes(a, b);
+    let different = only_a + only_b;
+    if different == 0 { 0.0 } else { different as f64 / (same + different) as f64 }
+}
+
+/// Share of the letter guesses two programs disagree on, given their outputs on the same partial
+/// words.
+pub fn behavioral_distance(a: &[u32], b: &[u32]) -> f64 {
+    if a.is_empty() {
+        return 0.0;
+    }
+    let differing:u32 = a.iter().zip(b).map(|(a, b)| ((a ^ b) & LETTER_BITS).count_ones()).sum();
+    f64::from(differing) / (a.len() * 26) as f64
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum DistanceTy {
+    Genotypic,
+    Behavioral,
+}
+
+impl FromStr for DistanceTy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "genotypic" => Ok(DistanceTy::Genotypic),
+            "behavioral" => Ok(DistanceTy::Behavioral),
+            _ => Err(format!("Unknown distance {:?}, expected genotypic or behavioral", s)),
+        }
+    }
+}
+
+/// What a genome is compared to others by
+#[derive(Debug,Clone,PartialEq)]
+pub enum Profile {
+    Genes(Vec<Gene>),
+    /// Outputs on the run's behavior words
+    Outputs(Vec<u32>),
+}
+
+impl Profile {
+    pub fn distance(&self, other: &Profile) -> f64 {
+        match (self, other) {
+            (Profile::Genes(a), Profile::Genes(b)) => genotypic_distance(a, b),
+            (Profile::Outputs(a), Profile::Outputs(b)) => behavioral_distance(a, b),
+            _ => panic!("Can't compare genes to outputs"),
+        }
+    }
+}
+
+/// Most genomes `diversity` looks at, since it compares every pair
+pub const DIVERSITY_SAMPLE:usize = 100;
+
+/// Mean distance between every pair of genomes, 0 for a pool of clones.
+pub fn diversity(profiles: &[Profile]) -> f64 {
+    let mut sum = 0.0;
+    for (i, a) in profiles.iter().enumerate() {
+        for b in &profiles[i+1..] {
+            sum += a.distance(b);
+        }
+    }
+    let pairs = profiles.len() * profiles.len().saturating_sub(1) / 2;
+    if pairs == 0 { 0.0 } else { sum / pairs as f64 }
+}
+
+/// Keeps the pool from filling up with copies of whatever's doing best.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Niching {
+    Off,
+    /// Before selection each fitness is multiplied by how crowded its genome's neighbourhood is:
+    /// the sum over the island of `1 - distance/radius` for every genome closer than `radius`,
+    /// itself included.
+    Sharing { radius: f64 },
+    /// Survivors are only parents. Each child replaces whichever of `factor` genomes drawn from
+    /// the island it's closest to, never the island's best.
+    Crowding { factor: usize },
+}
+
+/// Multiplies each fitness by its niche count, which is at least 1, so lower is still better. A
+/// genome always counts itself as 1, whatever the radius.
+pub fn share(fitness: &mut [f64], profiles: &[Profile], radius: f64) {
+    let counts:Vec<f64> = profiles.iter().enumerate().map(|(i, a)| {
+        let others:f64 = profiles.iter().enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, b)| a.distance(b))
+            .filter(|d| *d < radius)
+            .map(|d| 1.0 - d/radius)
+            .sum();
+        1.0 + others
+    }).collect();
+    for (f, count) in fitness.iter_mut().zip(counts) {
+        *f *= count;
+    }
+}
+
+/// Which of `factor` genomes drawn from `profiles[1..]` is closest to `child`. `profiles` has to
+/// have at least 2.
+pub fn crowded(child: &Profile, profiles: &[Profile], factor: usize, rng: &mut StdRng) -> usize {
+    let mut best = (f64::INFINITY, 1);
+    for _ in 0..factor.max(1) {
+        let i = rng.gen_range(1..profiles.len());
+        let d = child.distance(&profiles[i]);
+        if d < best.0 {
+            best = (d, i);
+        }
+    }
+    best.1
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum NichingTy {
+    Off,
+    Sharing,
+    Crowding,
+}
+
+impl FromStr for NichingTy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(NichingTy::Off),
+            "sharing" => Ok(NichingTy::Sharing),
+            "crowding" => Ok(NichingTy::Crowding),
+            _ => Err(format!("Unknown niching {:?}, expected off, sharing or crowding", s)),
+        }
+    }
+}
+
+pub fn from_opts(opts: &EvolveOpts) -> Niching {
+    match opts.niching {
+        NichingTy::Off => Niching::Off,
+        NichingTy::Sharing => Niching::Sharing{ radius: opts.niche_radius },
+        NichingTy::Crowding => Niching::Crowding{ factor: opts.crowding_factor },
+    }
+}
+
+#[cfg(test)]
+mod niching_test {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn genes(s: &str) -> Vec<Gene> {
+        s.split(',').enumerate().map(|(i, ins)| Gene{ order: i as f64, ins: ins.trim().parse().unwrap() }).collect()
+    }
+
+    #[test]
+    fn distances() {
+        let a = genes("xor 0 1, add 2 3, shl 0 4");
+        let b = genes("xor 0 1, add 2 4, shl 0 4");
+        assert_eq!(genotypic_distance(&a, &a), 0.0);
+        // the two adds are different genes, so 2 of the 4 differ
+        assert_eq!(genotypic_distance(&a, &b), 0.5);
+        assert_eq!(genotypic_distance(&a, &[]), 1.0);
+        assert_eq!(genotypic_distance(&[], &[]), 0.0);
+
+        assert_eq!(behavioral_distance(&[0b110, 1 << 26], &[0b110, 1 << 26]), 0.0);
+        // bit 0 and anything past z aren't letters
+        assert_eq!(behavioral_distance(&[0b1, 1 << 27], &[0, 0]), 0.0);
+        assert_eq!(behavioral_distance(&[LETTER_BITS, 0], &[0, 0]), 0.5);
+    }
+
+    #[test]
+    fn diversity_of_clones_is_zero() {
+        let a = Profile::Genes(genes("xor 0 1, add 2 3"));
+        assert_eq!(diversity(&[a.clone(), a.clone(), a.clone()]), 0.0);
+        assert_eq!(diversity(std::slice::from_ref(&a)), 0.0);
+        let b = Profile::Genes(genes("ldi 1 1"));
+        assert_eq!(diversity(&[a.clone(), a, b]), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn sharing_penalizes_crowds() {
+        let a = Profile::Outputs(vec![0]);
+        let b = Profile::Outputs(vec![LETTER_BITS]);
+        let mut fitness = vec![1.0, 1.0, 1.0, 5.0];
+        share(&mut fitness, &[a.clone(), a.clone(), a, b], 0.5);
+        // three clones count each other fully, the loner only itself
+        assert_eq!(fitness, vec![3.0, 3.0, 3.0, 5.0]);
+
+        // with no radius nothing is close enough to share with, so nothing changes
+        let mut fitness = vec![1.0, 2.0];
+        share(&mut fitness, &[Profile::Outputs(vec![0]), Profile::Outputs(vec![0])], 0.0);
+        assert_eq!(fitness, vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn crowding_replaces_the_closest() {
+        let profiles:Vec<Profile> = (0..10).map(|i| Profile::Outputs(vec![1 << (i + 1)])).collect();
+        let mut rng = StdRng::seed_from_u64(0);
+        // with enough draws the clone is always found, and the best is never picked
+        for _ in 0..20 {
+            assert_eq!(crowded(&profiles[7], &profiles, 100, &mut rng), 7);
+            assert_ne!(crowded(&profiles[0], &profiles, 3, &mut rng), 0);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+// Shrinks evolved programs without changing what they leave in the output register.
+//
+// Each pass works out which register values are known constants at each instruction and which
+// registers are still needed at each instruction, then rewrites or drops instructions that don't
+// matter. Passes repeat until nothing changes.
+//
+// The one thing to be careful of is that Jis/Jns skip whatever instruction comes next, so
+// removing the instruction right after a jump would make it skip a different one instead. Only
+// instructions that don't follow a jump (or are last) are removed; a jump whose next instruction
+// is pointless gets removed first, which frees that instruction up for the next pass.
+//
+// Skn and Lop move execution further than that, and programs using them are left as they are.
+
+use super::svm::*;
+
+type Known = Vec<Option<u32>>;
+
+/// Registers the instruction reads, as (dest, src).
+fn reads(ins: SvmInstruction) -> (Option<u8>, Option<u8>) {
+    use SvmInstructionTy::*;
+    match ins.ty {
+        Xor | Sub if ins.dest == ins.src => (None, None),
+        Xor | Add | Sub | And | Oor | Cmv => (Some(ins.dest), Some(ins.src)),
+        Mov | Pop | Lsb | Not => (None, Some(ins.src)),
+        Shl | Shr | Rol | Seb | Clb | Jis | Jns | Skn | Lop => (Some(ins.dest), None),
+        Ldi => (None, None),
+    }
+}
+
+/// The value the instruction leaves in its dest register, if it can be worked out from the known
+/// registers.
+fn known_result(ins: SvmInstruction, known: &Known) -> Option<u32> {
+    let (dest, src) = reads(ins);
+    let dest = match dest { Some(r) => known[r as usize]?, None => 0 };
+    let src = match src { Some(r) => known[r as usize]?, None => 0 };
+    ins.result(dest, src)
+}
+
+/// Whether the instruction leaves everything as it was, whatever the unknown registers hold.
+fn is_identity(ins: SvmInstruction, known: &Known) -> bool {
+    use SvmInstructionTy::*;
+    let src = if ins.ty.src_is_register() { known[ins.src as usize] } else { None };
+    match ins.ty {
+        Mov => ins.dest == ins.src,
+        And => ins.dest == ins.src || src == Some(u32::MAX),
+        Oor | Cmv => ins.dest == ins.src || src == Some(0),
+        Add | Sub | Xor => src == Some(0),
+        Shl | Shr | Rol => ins.src == 0,
+        Seb | Clb | Ldi | Pop | Lsb | Not | Jis | Jns | Skn | Lop => false,
+    }
+}
+
+fn merge(into: &mut Option<Known>, from: &Known) {
+    match into {
+        None => *into = Some(from.clone()),
+        Some(into) => for (a, b) in into.iter_mut().zip(from) {
+            if *a != *b { *a = None; }
+        },
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum Action {
+    Keep,
+    /// Has no effect on the output
+    Useless,
+    /// A jump that always skips; it and the instruction it skips can both go
+    AlwaysSkips,
+}
+
+/// Propagates known register values forward, rewriting instructions whose result is a constant
+/// into a cheaper form. Returns whether anything was rewritten.
+fn fold_constants(prog: &mut [(usize, SvmInstruction)], actions: &mut [Action], config: &SvmConfig) -> bool {
+    let len = prog.len();
+    let mut changed = false;
+    // None means no path reaches the instruction
+    let mut known_in:Vec<Option<Known>> = vec![None; len + 1];
+    let mut init:Known = vec![Some(0); config.num_registers];
+    for reg in config.inputs() {
+        init[reg] = None;
+    }
+    known_in[0] = Some(init);
+    for i in 0..len {
+        let known = match known_in[i].take() { Some(k) => k, None => continue };
+        let ins = prog[i].1;
+        if ins.is_jump() {
+            let skip_to = (i+2).min(len);
+            match known[ins.dest as usize].map(|v| ins.skips(v)) {
+                Some(false) => actions[i] = Action::Useless,
+                Some(true) => actions[i] = Action::AlwaysSkips,
+                None => (),
+            }
+            if actions[i] != Action::AlwaysSkips { merge(&mut known_in[i+1], &known); }
+            if actions[i] != Action::Useless { merge(&mut known_in[skip_to], &known); }
+            continue;
+        }
+        let before = known[ins.dest as usize];
+        let mut after = known_result(ins, &known);
+        if is_identity(ins, &known) || (after.is_some() && after == before) {
+            actions[i] = Action::Useless;
+            after = before;
+        } else if let Some(after) = after {
+            let dest = ins.dest;
+            let zero = SvmInstruction{ty: SvmInstructionTy::Xor, dest, src: dest};
+            let simpler = match before {
+                _ if after == 0 => Some(zero),
+                Some(before) if (after ^ before).count_ones() == 1 => {
+                    let bit = (after ^ before).trailing_zeros() as u8;
+                    let ty = if after & (1 << bit) != 0 { SvmInstructionTy::Seb } else { SvmInstructionTy::Clb };
+                    Some(SvmInstruction{ty, dest, src: bit})
+                },
+                _ => None,
+            };
+            if let Some(simpler) = simpler {
+                if simpler != ins {
+                    prog[i].1 = simpler;
+                    changed = true;
+                }
+            }
+        }
+        let mut known = known;
+        known[ins.dest as usize] = after;
+        merge(&mut known_in[i+1], &known);
+    }
+    changed
+}
+
+fn follows_jump(prog: &[(usize, SvmInstruction)], i: usize) -> bool {
+    i > 0 && prog[i-1].1.is_jump()
+}
+
+/// Whether `remove` can take out a useless instruction.
+fn removable(prog: &[(usize, SvmInstruction)], i: usize) -> bool {
+    !follows_jump(prog, i) || i+1 == prog.len()
+}
+
+/// Marks instructions whose result never makes it to the output register as useless.
+fn mark_dead(prog: &[(usize, SvmInstruction)], actions: &mut [Action], config: &SvmConfig) {
+    let len = prog.len();
+    let mut output = vec![false; config.num_registers];
+    output[usize::from(config.output)] = true;
+    // live_in[i] is the registers still needed just before instruction i
+    let mut live_in = vec![output; len + 1];
+    for i in (0..len).rev() {
+        let ins = prog[i].1;
+        let skipped = &live_in[(i+2).min(len)];
+        // Anything that stays in the program still needs what it reads, since whatever made it
+        // useless may have depended on those values.
+        let mut live = match actions[i] {
+            Action::Useless if removable(prog, i) => {
+                live_in[i] = live_in[i+1].clone();
+                continue;
+            },
+            Action::AlwaysSkips if !follows_jump(prog, i) => {
+                live_in[i] = skipped.clone();
+                continue;
+            },
+            Action::AlwaysSkips => skipped.clone(),
+            Action::Keep | Action::Useless => live_in[i+1].clone(),
+        };
+        if ins.is_jump() {
+            if actions[i] != Action::AlwaysSkips {
+                for (l, s) in live.iter_mut().zip(skipped) {
+                    *l |= s;
+                }
+            }
+        } else if live[ins.dest as usize] {
+            live[ins.dest as usize] = false;
+        } else {
+            actions[i] = Action::Useless;
+            live_in[i] = live;
+            continue;
+        }
+        let (dest, src) = reads(ins);
+        for r in dest.into_iter().chain(src) {
+            live[r as usize] = true;
+        }
+        live_in[i] = live;
+    }
+    // A jump over an instruction that doesn't matter (or over nothing) doesn't matter either
+    for i in 0..len {
+        if prog[i].1.is_jump() && actions[i] == Action::Keep && (i+1 == len || actions[i+1] == Action::Useless) {
+            actions[i] = Action::Useless;
+        }
+    }
+}
+
+/// Removes what can safely be removed this pass; returns whether anything was.
+fn remove(prog: &mut Vec<(usize, SvmInstruction)>, actions: &[Action]) -> bool {
+    let len = prog.len();
+    let mut remove = vec![false; len];
+    for i in 0..len {
+        match actions[i] {
+            Action::Useless if removable(prog, i) => remove[i] = true,
+            Action::AlwaysSkips if !follows_jump(prog, i) => {
+                remove[i] = true;
+                if i+1 < len { remove[i+1] = true; }
+            },
+            _ => (),
+        }
+    }
+    let before = prog.len();
+    let mut i = 0;
+    prog.retain(|_| (!remove[i], i += 1).0);
+    prog.len() != before
+}
+
+/// Returns a shorter program that leaves the same value in the output register for any inputs,
+/// with every register other than the inputs starting at 0. Each instruction is returned with its
+/// index in `program`, so callers can keep whatever they had attached to it.
+pub fn optimize(program: &[SvmInstruction], config: &SvmConfig) -> Vec<(usize, SvmInstruction)> {
+    let mut prog:Vec<(usize, SvmInstruction)> = program.iter().copied().enumerate().collect();
+    if program.iter().any(|ins| ins.is_control() && !ins.is_jump()) {
+        return prog;
+    }
+    loop {
+        let mut actions = vec![Action::Keep; prog.len()];
+        let rewritten = fold_constants(&mut prog, &mut actions, config);
+        mark_dead(&prog, &mut actions, config);
+        let removed = remove(&mut prog, &actions);
+        if !rewritten && !removed {
+            return prog;
+        }
+    }
+}
+
+#[cfg(test)]
+mod optimize_test {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn parse(text: &str) -> Vec<SvmInstruction> {
+        text.lines().map(|l| l.parse().unwrap()).collect()
+    }
+
+    fn config(num_inputs: usize) -> SvmConfig {
+        let mut config = SvmConfig::original(num_inputs);
+        config.instruction_set = InstructionSet::all();
+        config
+    }
+
+    fn run(program: &[SvmInstruction], config: &SvmConfig, inputs: &[u32]) -> u32 {
+        let mut state = SvmState::new(program, config);
+        state.memory_mut()[config.inputs()].copy_from_slice(inputs);
+        while state.step() == StepResult::Continue {}
+        state.memory()[usize::from(config.output)]
+    }
+
+    fn optimized(program: &[SvmInstruction], num_inputs: usize) -> Vec<SvmInstruction> {
+        optimize(program, &config(num_inputs)).into_iter().map(|(_, ins)| ins).collect()
+    }
+
+    #[test]
+    fn removes_dead_writes() {
+        let program = parse("xor 5 1\nmov 0 2\nadd 6 0\noor 0 3\nshl 7 1");
+        assert_eq!(optimized(&program, 3), parse("mov 0 2\noor 0 3"));
+    }
+
+    #[test]
+    fn removes_pointless_jumps() {
+        let program = parse("mov 0 1\njis 2 3\nxor 9 1\nshl 0 0\njns 1 1");
+        assert_eq!(optimized(&program, 2), parse("mov 0 1"));
+    }
+
+    #[test]
+    fn keeps_skipped_instruction_position() {
+        // removing the dead xor would make the jis skip the mov instead
+        let program = parse("jis 1 3\nxor 9 1\nmov 0 2");
+        assert_eq!(optimized(&program, 2), parse("mov 0 2"));
+        let program = parse("jis 1 3\nmov 0 2\nxor 9 1");
+        assert_eq!(optimized(&program, 2), parse("jis 1 3\nmov 0 2"));
+    }
+
+    #[test]
+    fn folds_constants() {
+        let program = parse("seb 10 4\nshl 10 1\nmov 0 10\njis 10 5\nseb 0 1\nadd 0 1");
+        assert_eq!(optimized(&program, 1), parse("seb 0 5\nadd 0 1"));
+    }
+
+    #[test]
+    fn folds_extensions() {
+        let program = parse("ldi 7 5\nrol 7 30\nnot 8 7\nlsb 9 8\ncmv 0 9\npop 10 9\nadd 0 10");
+        assert_eq!(optimized(&program, 1), parse("seb 0 1\nseb 0 0"));
+        let program = parse("skn 1 1\nxor 9 1\nmov 0 2");
+        assert_eq!(optimized(&program, 2), program);
+    }
+
+    #[test]
+    fn random_programs_keep_their_output() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut small = config(4);
+        small.num_registers = 12;
+        small.output = 11;
+        small.first_input = 3;
+        for i in 0..1500 {
+            let config = match i % 3 {
+                0 => SvmConfig::original(10),
+                1 => config(10),
+                _ => small,
+            };
+            let len = rng.gen_range(0..60);
+            let program:Vec<_> = (0..len)
+                .map(|_| SvmInstruction::random(&mut rng, &config))
+                .filter(|ins| !matches!(ins.ty, SvmInstructionTy::Skn | SvmInstructionTy::Lop))
+                .collect();
+            let optimized:Vec<_> = optimize(&program, &config).into_iter().map(|(_, ins)| ins).collect();
+            assert!(optimized.len() <= program.len());
+            for _ in 0..50 {
+                let inputs:Vec<u32> = config.inputs().map(|_| 1 << rng.gen_range(0..27)).collect();
+                assert_eq!(
+                    run(&program, &config, &inputs), run(&optimized, &config, &inputs),
+                    "{:?} -> {:?}", program, optimized,
+                );
+            }
+        }
+    }
+}
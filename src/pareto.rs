@@ -0,0 +1,254 @@
+// Rankings that compare genomes on false positives, false negatives and length separately instead
+// of weighing them up into one fitness. NSGA-II style, for evolving without settling on weights up
+// front, sorts by how many fronts of genomes beat them, then by how far they are from their
+// neighbours on their own front. Lexicographic, for when any false negative rules a genome out,
+// sorts by false negatives, then false positives, then length.
+
+use std::fmt;
+use serde_derive::{Serialize,Deserialize};
+
+use super::Genome;
+use super::cli::EvolveOpts;
+
+/// False positives and false negatives per partial word, and instructions. Lower is better for
+/// each.
+#[derive(Debug,Clone,Copy,PartialEq,Default,Serialize,Deserialize)]
+pub struct Objectives(pub [f64; 3]);
+
+impl Objectives {
+    pub fn feasible(&self) -> bool {
+        self.0[1] == 0.0
+    }
+
+    /// At least as good on every objective and better on one
+    pub fn dominates(&self, other: &Objectives) -> bool {
+        self.0.iter().zip(&other.0).all(|(a, b)| a <= b) && self.0.iter().zip(&other.0).any(|(a, b)| a < b)
+    }
+}
+
+impl fmt::Display for Objectives {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "false positives {:.4}, false negatives {:.4}, instructions {}", self.0[0], self.0[1], self.0[2])
+    }
+}
+
+/// Front of each: 0 if nothing dominates it, 1 if only front 0 does, and so on.
+pub fn ranks(objectives: &[Objectives]) -> Vec<usize> {
+    let len = objectives.len();
+    let mut beaten_by = vec![0usize; len];
+    let mut beats:Vec<Vec<usize>> = vec![Vec::new(); len];
+    for i in 0..len {
+        for j in i+1..len {
+            if objectives[i].dominates(&objectives[j]) {
+                beats[i].push(j);
+                beaten_by[j] += 1;
+            } else if objectives[j].dominates(&objectives[i]) {
+                beats[j].push(i);
+                beaten_by[i] += 1;
+            }
+        }
+    }
+    let mut ranks = vec![0; len];
+    let mut front:Vec<usize> = (0..len).filter(|i| beaten_by[*i] == 0).collect();
+    let mut rank = 0;
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for &i in &front {
+            ranks[i] = rank;
+            for &j in &beats[i] {
+                beaten_by[j] -= 1;
+                if beaten_by[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        front = next;
+        rank += 1;
+    }
+    ranks
+}
+
+/// Positions of the genomes nothing dominates
+pub fn front(objectives: &[Objectives]) -> Vec<usize> {
+    ranks(objectives).iter().enumerate().filter(|(_, r)| **r == 0).map(|(i, _)| i).collect()
+}
+
+/// For each of `members`, the sum over objectives of the gap between its neighbours on either side,
+/// as a share of the members' range. The ends of each objective get infinity so they're kept,
+/// unless the members all tie on it.
+fn crowding_distances(objectives: &[Objectives], members: &[usize]) -> Vec<f64> {
+    let mut distances = vec![0.0; members.len()];
+    for o in 0..3 {
+        let mut order:Vec<usize> = (0..members.len()).collect();
+        order.sort_by(|a,b| objectives[members[*a]].0[o].partial_cmp(&objectives[members[*b]].0[o]).unwrap());
+        let value = |i: usize| objectives[members[order[i]]].0[o];
+        let range = value(members.len() - 1) - value(0);
+        if range > 0.0 {
+            distances[order[0]] = f64::INFINITY;
+            distances[order[members.len() - 1]] = f64::INFINITY;
+            for i in 1..members.len().saturating_sub(1) {
+                distances[order[i]] += (value(i + 1) - value(i - 1)) / range;
+            }
+        }
+    }
+    distances
+}
+
+/// Sorts genomes by front, then most spread out first within a front, so taking from the start
+/// keeps the best trade-offs and as wide a range of them as it can.
+pub fn sort(pool: &mut [Genome]) {
+    if pool.is_empty() {
+        return;
+    }
+    let objectives:Vec<Objectives> = pool.iter().map(|g| g.objectives).collect();
+    let ranks = ranks(&objectives);
+    let mut distances = vec![0.0; pool.len()];
+    for rank in 0..=*ranks.iter().max().unwrap() {
+        let members:Vec<usize> = (0..pool.len()).filter(|i| ranks[*i] == rank).collect();
+        for (i, d) in members.iter().zip(crowding_distances(&objectives, &members)) {
+            distances[*i] = d;
+        }
+    }
+    let mut order:Vec<usize> = (0..pool.len()).collect();
+    order.sort_by(|a,b| ranks[*a].cmp(&ranks[*b]).then(distances[*b].partial_cmp(&distances[*a]).unwrap()));
+    let sorted:Vec<Genome> = order.iter().map(|i| pool[*i].clone()).collect();
+    for (slot, genome) in pool.iter_mut().zip(sorted) {
+        *slot = genome;
+    }
+}
+
+fn lexicographic_key(g: &Genome) -> [f64; 3] {
+    [g.objectives.0[1], g.objectives.0[0], g.objectives.0[2]]
+}
+
+/// Sorts genomes with no false negatives first, by false positives then length, and the rest after
+/// them by how many false negatives they have, so there's still something to select on before any
+/// genome gets there.
+pub fn sort_lexicographic(pool: &mut [Genome]) {
+    pool.sort_by(|a,b| lexicographic_key(a).partial_cmp(&lexicographic_key(b)).unwrap());
+}
+
+/// How each island is put in order, best first, for selection
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Ranking {
+    Fitness,
+    Pareto,
+    Lexicographic,
+}
+
+impl Ranking {
+    pub fn sort(self, pool: &mut [Genome]) {
+        match self {
+            Ranking::Fitness => pool.sort_by(|a,b| a.fitness.partial_cmp(&b.fitness).unwrap()),
+            Ranking::Pareto => sort(pool),
+            Ranking::Lexicographic => sort_lexicographic(pool),
+        }
+    }
+
+    /// The one genome to show for the whole pool. A front has no single best, so with Pareto
+    /// ranking it's the same as lexicographic: fewest false negatives, then false positives, then
+    /// instructions, which is always on the front.
+    pub fn best(self, pool: &[Genome]) -> &Genome {
+        match self {
+            Ranking::Fitness => pool.iter().min_by(|a,b| a.fitness.partial_cmp(&b.fitness).unwrap()).unwrap(),
+            Ranking::Pareto | Ranking::Lexicographic => pool.iter()
+                .min_by(|a,b| lexicographic_key(a).partial_cmp(&lexicographic_key(b)).unwrap())
+                .unwrap(),
+        }
+    }
+}
+
+pub fn from_opts(opts: &EvolveOpts) -> Ranking {
+    if opts.pareto {
+        Ranking::Pareto
+    } else if opts.zero_false_negatives {
+        Ranking::Lexicographic
+    } else {
+        Ranking::Fitness
+    }
+}
+
+#[cfg(test)]
+mod pareto_test {
+    use super::*;
+    use crate::mutation::PointRates;
+
+    fn genome(objectives: [f64; 3]) -> Genome {
+        Genome{
+            instructions: vec![],
+            mutation_rate: 0.5,
+            point_rates: PointRates::default(),
+            fitness: 0.0,
+            objectives: Objectives(objectives),
+        }
+    }
+
+    #[test]
+    fn dominates() {
+        let a = Objectives([1.0, 1.0, 10.0]);
+        assert!(a.dominates(&Objectives([1.0, 2.0, 10.0])));
+        assert!(!a.dominates(&a));
+        assert!(!a.dominates(&Objectives([0.5, 2.0, 10.0])));
+    }
+
+    #[test]
+    fn ranks_and_front() {
+        let objectives = [
+            Objectives([1.0, 3.0, 5.0]),
+            Objectives([3.0, 1.0, 5.0]),
+            Objectives([2.0, 4.0, 5.0]),
+            Objectives([3.0, 3.0, 6.0]),
+            Objectives([1.0, 3.0, 5.0]),
+        ];
+        // clones don't beat each other
+        assert_eq!(ranks(&objectives), vec![0, 0, 1, 1, 0]);
+        assert_eq!(front(&objectives), vec![0, 1, 4]);
+        assert_eq!(front(&[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn sort_keeps_ends_of_the_front() {
+        let mut pool:Vec<Genome> = [
+            [5.0, 5.0, 5.0],
+            [2.0, 2.0, 2.0],
+            [1.0, 3.0, 2.0],
+            [1.5, 2.5, 2.0],
+            [1.4, 2.6, 2.0],
+            [3.0, 1.0, 2.0],
+        ].iter().map(|o| genome(*o)).collect();
+        sort(&mut pool);
+        let order:Vec<[f64; 3]> = pool.iter().map(|g| g.objectives.0).collect();
+        // the two ends first, then the middle in order of how far it is from its neighbours, the
+        // dominated one last
+        assert_eq!(order[..2], [[1.0, 3.0, 2.0], [3.0, 1.0, 2.0]]);
+        assert_eq!(order[5], [5.0, 5.0, 5.0]);
+        assert_eq!(order[2], [2.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn lexicographic() {
+        let mut pool:Vec<Genome> = [
+            [0.5, 0.0, 30.0],
+            [0.0, 2.0, 1.0],
+            [0.5, 0.0, 10.0],
+            [9.0, 0.5, 90.0],
+            [3.0, 0.0, 1.0],
+        ].iter().map(|o| genome(*o)).collect();
+        sort_lexicographic(&mut pool);
+        let order:Vec<[f64; 3]> = pool.iter().map(|g| g.objectives.0).collect();
+        assert_eq!(order, vec![
+            [0.5, 0.0, 10.0],
+            [0.5, 0.0, 30.0],
+            [3.0, 0.0, 1.0],
+            [9.0, 0.5, 90.0],
+            [0.0, 2.0, 1.0],
+        ]);
+        assert_eq!(pool.iter().filter(|g| g.objectives.feasible()).count(), 3);
+
+        // the fittest here misses letters
+        pool[4].fitness = -1.0;
+        assert_eq!(Ranking::Fitness.best(&pool).objectives.0, [0.0, 2.0, 1.0]);
+        assert_eq!(Ranking::Lexicographic.best(&pool).objectives.0, [0.5, 0.0, 10.0]);
+        assert_eq!(Ranking::Pareto.best(&pool).objectives.0, [0.5, 0.0, 10.0]);
+    }
+}
@@ -0,0 +1,172 @@
+// The word square search, with the letters allowed in each cell coming from the word map, an
+// evolved program, or both. Comparing the node counts and squares found against the word map alone
+// shows what a program would be worth as a pruning filter.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::*;
+
+/// How a program's guess is used for a cell's letters
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Filter {
+    /// Only letters both the program and the word map allow, so it can only cut the search down
+    Intersect,
+    /// The program's guess alone. Squares are only checked against the word map once they're
+    /// filled in.
+    Substitute,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "intersect" => Ok(Filter::Intersect),
+            "substitute" => Ok(Filter::Substitute),
+            _ => Err(format!("Unknown filter {:?}, expected intersect or substitute", s)),
+        }
+    }
+}
+
+pub struct SearchStats<const N: usize> {
+    /// Cells the search tried to fill, counting finished squares
+    pub nodes: u64,
+    pub squares: Vec<Square<N>>,
+    pub elapsed: Duration,
+}
+
+impl<const N: usize> fmt::Display for SearchStats<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} nodes, {:.3}s, {} squares", self.nodes, self.elapsed.as_secs_f64(), self.squares.len())
+    }
+}
+
+/// Fills in squares a cell at a time, row by row, starting from each of `first_rows`. `candidates`
+/// gives the letters that can come next in a partial row or column; a cell gets the ones allowed
+/// both across and down. With `check` a finished square is only kept if every row and column is
+/// in the word map.
+pub fn search<const N: usize>(
+    first_rows: &[Word<N>],
+    map: &WMap<N>,
+    candidates: &dyn Fn(&OWord<N>) -> CharSet,
+    check: bool,
+) -> SearchStats<N> {
+    let start = Instant::now();
+    let mut stats = SearchStats{ nodes: 0, squares: Vec::new(), elapsed: Duration::default() };
+    for word in first_rows {
+        let mut square:Square<N> = [[None; N]; N];
+        for i in 0..N {
+            square[0][i] = Some(word[i]);
+        }
+        recurse(square, 0, 1, map, candidates, check, &mut stats);
+    }
+    stats.elapsed = start.elapsed();
+    stats
+}
+
+fn recurse<const N: usize>(
+    s: Square<N>,
+    col: usize,
+    row: usize,
+    map: &WMap<N>,
+    candidates: &dyn Fn(&OWord<N>) -> CharSet,
+    check: bool,
+    stats: &mut SearchStats<N>,
+) {
+    stats.nodes += 1;
+    if row == N {
+        if !check || is_word_square(&s, map) {
+            stats.squares.push(s);
+        }
+        return;
+    }
+    let mut col_key:OWord<N> = [None; N];
+    let mut row_key:OWord<N> = [None; N];
+    row_key.copy_from_slice(&s[row]);
+    for i in 0..row {
+        col_key[i] = s[i][col];
+    }
+    let and_set = candidates(&col_key) & candidates(&row_key);
+    let new_col = (col+1) % N;
+    let new_row = if col == N-1 { row + 1 } else { row };
+    for c in and_set {
+        let mut new_s = s;
+        new_s[row][col] = Some(c);
+        recurse(new_s, new_col, new_row, map, candidates, check, stats);
+    }
+}
+
+/// Whether a word is in the map: its last letter is allowed after the rest
+fn is_word<const N: usize>(word: OWord<N>, map: &WMap<N>) -> bool {
+    let mut prefix = word;
+    let last = match prefix[N-1].take() {
+        Some(last) => last,
+        None => return false,
+    };
+    map.get(&prefix).map(|set| set.check(last)).unwrap_or_default()
+}
+
+fn is_word_square<const N: usize>(s: &Square<N>, map: &WMap<N>) -> bool {
+    (0..N).all(|i| is_word(s[i], map) && is_word(s.map(|row| row[i]), map))
+}
+
+/// Prints a square the way the search always has, a row per line with a blank line after
+pub fn print_square<const N: usize>(s: &Square<N>) {
+    for row in s {
+        let line:Vec<String> = row.iter().map(|c| c.map_or('.', char::from).to_string()).collect();
+        println!("{} ", line.join(" "));
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod search_test {
+    use super::*;
+
+    fn words() -> Vec<Word<3>> {
+        ["bat", "ace", "tee", "bit", "ice"].iter()
+            .map(|w| parse_oword::<3>(w).unwrap().map(Option::unwrap))
+            .collect()
+    }
+
+    #[test]
+    fn program_filters() {
+        let words = words();
+        let map = build_map(&words);
+        let lookup = |word: &OWord<3>| map.get(word).copied().unwrap_or_default();
+        let baseline = search(&words, &map, &lookup, false);
+        // ba./.c./.ee with ace or ice down the middle
+        assert_eq!(baseline.squares.len(), 4);
+
+        // guessing e everywhere as well finds the same squares, the long way round
+        let extra_e = |word: &OWord<3>| lookup(word).set(FChar::try_from('e').unwrap());
+        let substituted = search(&words, &map, &extra_e, true);
+        assert_eq!(substituted.squares, baseline.squares);
+        assert!(substituted.nodes > baseline.nodes);
+
+        // never guessing i after b rules out ice as the second row
+        let no_bi = |word: &OWord<3>| {
+            let set = lookup(word);
+            if word[0] == Some(FChar::try_from('b').unwrap()) && word[1].is_none() {
+                set.clear(FChar::try_from('i').unwrap())
+            } else {
+                set
+            }
+        };
+        let intersected = search(&words, &map, &no_bi, false);
+        assert_eq!(intersected.squares, [baseline.squares[0], baseline.squares[2]]);
+        assert!(intersected.nodes < baseline.nodes);
+    }
+
+    #[test]
+    fn word_squares() {
+        let map = build_map(&words());
+        let square = |rows: [&str; 3]| rows.map(|r| parse_oword::<3>(r).unwrap());
+        assert!(is_word_square(&square(["bat", "ace", "tee"]), &map));
+        assert!(is_word_square(&square(["bit", "ice", "tee"]), &map));
+        assert!(!is_word_square(&square(["bat", "bat", "bat"]), &map));
+        assert!(!is_word_square(&square(["bat", "ace", "te."]), &map));
+    }
+}
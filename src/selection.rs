@@ -0,0 +1,209 @@
+use std::fmt;
+use std::str::FromStr;
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use super::cli::EvolveOpts;
+
+/// Picks which genomes survive a round. The survivors are kept as they are and are the parents of
+/// the rest of the next round's pool.
+pub trait Selection: fmt::Debug + Send + Sync {
+    /// `fitness` is the pool's, sorted best (lowest) first. Returns whether each genome survives.
+    fn survivors(&self, fitness: &[f64], rng: &mut StdRng) -> Vec<bool>;
+}
+
+/// Each genome survives with a chance falling linearly from 1 for the best to 0 for the worst, so
+/// about half the pool survives.
+#[derive(Debug)]
+pub struct LinearRank;
+
+impl Selection for LinearRank {
+    fn survivors(&self, fitness: &[f64], rng: &mut StdRng) -> Vec<bool> {
+        let len = fitness.len() as f64;
+        (0..fitness.len()).map(|i| rng.gen::<f64>() > (i as f64)/len).collect()
+    }
+}
+
+/// Number of survivors for a fraction of the pool, at least 1
+fn survivor_count(len: usize, fraction: f64) -> usize {
+    ((len as f64 * fraction).round() as usize).clamp(1, len.max(1))
+}
+
+/// The best `fraction` of the pool survives.
+#[derive(Debug)]
+pub struct Truncation {
+    pub fraction: f64,
+}
+
+impl Selection for Truncation {
+    fn survivors(&self, fitness: &[f64], _rng: &mut StdRng) -> Vec<bool> {
+        let n = survivor_count(fitness.len(), self.fraction);
+        (0..fitness.len()).map(|i| i < n).collect()
+    }
+}
+
+/// Holds tournaments of `size` genomes drawn from the ones that haven't survived yet, the winner of
+/// each surviving, until `fraction` of the pool has.
+#[derive(Debug)]
+pub struct Tournament {
+    pub size: usize,
+    pub fraction: f64,
+}
+
+impl Selection for Tournament {
+    fn survivors(&self, fitness: &[f64], rng: &mut StdRng) -> Vec<bool> {
+        let mut res = vec![false; fitness.len()];
+        let mut left:Vec<usize> = (0..fitness.len()).collect();
+        for _ in 0..survivor_count(fitness.len(), self.fraction) {
+            // the pool is sorted, so the winner is the entrant that comes first
+            let winner = (0..self.size.max(1)).map(|_| rng.gen_range(0..left.len())).min().unwrap();
+            res[left.remove(winner)] = true;
+        }
+        res
+    }
+}
+
+/// Draws `fraction` of the pool without replacement, each with a chance proportional to
+/// 1/fitness, since lower is better.
+#[derive(Debug)]
+pub struct Proportional {
+    pub fraction: f64,
+}
+
+impl Selection for Proportional {
+    fn survivors(&self, fitness: &[f64], rng: &mut StdRng) -> Vec<bool> {
+        let mut res = vec![false; fitness.len()];
+        let mut weights:Vec<f64> = fitness.iter().map(|f| 1.0 / f.max(f64::MIN_POSITIVE)).collect();
+        for _ in 0..survivor_count(fitness.len(), self.fraction) {
+            let total:f64 = weights.iter().sum();
+            let mut target = rng.gen::<f64>() * total;
+            // rounding can run off the end, which goes to the last one still in
+            let mut pick = weights.iter().rposition(|w| *w > 0.0).unwrap();
+            for (i, w) in weights.iter().enumerate() {
+                if *w > 0.0 && target < *w {
+                    pick = i;
+                    break;
+                }
+                target -= w;
+            }
+            res[pick] = true;
+            weights[pick] = 0.0;
+        }
+        res
+    }
+}
+
+/// The best `count` genomes always survive, and `rest` picks from the others.
+#[derive(Debug)]
+pub struct Elitism {
+    pub count: usize,
+    pub rest: Box<dyn Selection>,
+}
+
+impl Selection for Elitism {
+    fn survivors(&self, fitness: &[f64], rng: &mut StdRng) -> Vec<bool> {
+        let count = self.count.min(fitness.len());
+        let mut res = vec![true; count];
+        if count < fitness.len() {
+            res.extend(self.rest.survivors(&fitness[count..], rng));
+        }
+        res
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum SelectionTy {
+    LinearRank,
+    Tournament,
+    Truncation,
+    Proportional,
+}
+
+impl FromStr for SelectionTy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "linear-rank" => Ok(SelectionTy::LinearRank),
+            "tournament" => Ok(SelectionTy::Tournament),
+            "truncation" => Ok(SelectionTy::Truncation),
+            "proportional" => Ok(SelectionTy::Proportional),
+            _ => Err(format!("Unknown selection {:?}, expected linear-rank, tournament, truncation or proportional", s)),
+        }
+    }
+}
+
+pub fn from_opts(opts: &EvolveOpts) -> Box<dyn Selection> {
+    let fraction = opts.survivors;
+    let selection:Box<dyn Selection> = match opts.selection {
+        SelectionTy::LinearRank => Box::new(LinearRank),
+        SelectionTy::Tournament => Box::new(Tournament{ size: opts.tournament_size, fraction }),
+        SelectionTy::Truncation => Box::new(Truncation{ fraction }),
+        SelectionTy::Proportional => Box::new(Proportional{ fraction }),
+    };
+    if opts.elitism == 0 {
+        selection
+    } else {
+        Box::new(Elitism{ count: opts.elitism, rest: selection })
+    }
+}
+
+#[cfg(test)]
+mod selection_test {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn fitness() -> Vec<f64> {
+        (1..=100).map(f64::from).collect()
+    }
+
+    fn kept(survivors: &[bool]) -> Vec<usize> {
+        survivors.iter().enumerate().filter(|(_, s)| **s).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn linear_rank_matches_original() {
+        // what evolve did before selection was configurable
+        let mut rng = StdRng::seed_from_u64(3);
+        let pool:Vec<usize> = (0..100).filter(|i| rng.gen::<f64>() > (*i as f64)/100.0).collect();
+        let survivors = LinearRank.survivors(&fitness(), &mut StdRng::seed_from_u64(3));
+        assert_eq!(kept(&survivors), pool);
+    }
+
+    #[test]
+    fn fractions() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(kept(&Truncation{ fraction: 0.25 }.survivors(&fitness(), &mut rng)), (0..25).collect::<Vec<_>>());
+        assert_eq!(kept(&Truncation{ fraction: 0.0 }.survivors(&fitness(), &mut rng)), vec![0]);
+        for _ in 0..20 {
+            assert_eq!(kept(&Tournament{ size: 4, fraction: 0.3 }.survivors(&fitness(), &mut rng)).len(), 30);
+            assert_eq!(kept(&Proportional{ fraction: 0.3 }.survivors(&fitness(), &mut rng)).len(), 30);
+        }
+        assert_eq!(kept(&Tournament{ size: 1, fraction: 1.0 }.survivors(&fitness(), &mut rng)).len(), 100);
+        assert_eq!(kept(&Proportional{ fraction: 1.0 }.survivors(&fitness(), &mut rng)).len(), 100);
+    }
+
+    #[test]
+    fn pressure() {
+        // bigger tournaments and fitter genomes mean better survivors on average
+        let mut rng = StdRng::seed_from_u64(1);
+        let mean = |s: &dyn Selection, rng: &mut StdRng| {
+            let kept:Vec<usize> = (0..50).flat_map(|_| kept(&s.survivors(&fitness(), rng))).collect();
+            kept.iter().sum::<usize>() as f64 / kept.len() as f64
+        };
+        let small = mean(&Tournament{ size: 2, fraction: 0.2 }, &mut rng);
+        let big = mean(&Tournament{ size: 8, fraction: 0.2 }, &mut rng);
+        let proportional = mean(&Proportional{ fraction: 0.2 }, &mut rng);
+        assert!(big < small && small < 49.5, "{} {}", big, small);
+        assert!(proportional < 49.5, "{}", proportional);
+    }
+
+    #[test]
+    fn elitism() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let selection = Elitism{ count: 10, rest: Box::new(Truncation{ fraction: 0.1 }) };
+        assert_eq!(kept(&selection.survivors(&fitness(), &mut rng)), (0..19).collect::<Vec<_>>());
+        let selection = Elitism{ count: 200, rest: Box::new(LinearRank) };
+        assert_eq!(kept(&selection.survivors(&fitness(), &mut rng)).len(), 100);
+    }
+}
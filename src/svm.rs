@@ -1,15 +1,24 @@
 use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 use rand::Rng;
 use serde_derive::{Deserialize,Serialize};
 // The Shelvacu Fast Word Square VM: SFWSVM -> SVM
-// registers (32-bit each)
+// registers (32-bit each), laid out by `SvmConfig`. Originally, and by default:
 // 00 output
-// 01..16 input
-// 16..64 work registers, initialized to 0
+// 01..=N input, one per letter of the word (N is the word size)
+// the rest up to 31 are work registers, initialized to 0
+//
+// The first 12 instructions are the original set. The rest are extensions, which evolution only
+// uses if they're turned on in the run's `InstructionSet`; none of them change how the original
+// ones behave, so programs written before they existed run exactly as they did.
 
-const SVM_NUM_REGISTERS:usize = 32;
+/// Operands are a byte, so this is as many registers as an instruction can name.
+pub const SVM_MAX_REGISTERS:usize = 256;
+/// Shift amounts and bit numbers are below this, whatever the number of registers.
+pub const SVM_REGISTER_BITS:u8 = 32;
 type SvmRegister = u32;
-type SvmMemory = Box<[SvmRegister; SVM_NUM_REGISTERS]>;
+type SvmMemory = Box<[SvmRegister]>;
 
 #[derive(Debug,PartialEq,Eq,Clone,Copy,PartialOrd,Ord,Serialize,Deserialize)]
 pub enum SvmInstructionTy {
@@ -25,25 +34,102 @@ pub enum SvmInstructionTy {
     Clb, //clear bit
     Jis, //jump forward one instruction if bit is set
     Jns, //jump if not set
+    // extensions
+    Ldi, //load immediate: dest = src as a number
+    Pop, //popcount of src
+    Lsb, //lowest set bit of src, as a mask
+    Rol, //rotate left
+    Not, //bitwise not of src
+    Cmv, //move src to dest if src is nonzero
+    Skn, //skip the next src instructions if dest is zero
+    Lop, //jump back to the instruction src+1 before this one if dest is nonzero, within the step budget
 }
 
 impl SvmInstructionTy {
-    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+    /// Every instruction, in opcode order
+    pub const ALL:[SvmInstructionTy; 20] = {
         use SvmInstructionTy::*;
-        match rng.gen_range(0..12) {
-            0 => Xor,
-            1 => Add,
-            2 => Sub,
-            3 => And,
-            4 => Oor,
-            5 => Mov,
-            6 => Shl,
-            7 => Shr,
-            8 => Seb,
-            9 => Clb,
-            10=> Jis,
-            11=> Jns,
-            _ => unreachable!(),
+        [Xor, Add, Sub, And, Oor, Mov, Shl, Shr, Seb, Clb, Jis, Jns, Ldi, Pop, Lsb, Rol, Not, Cmv, Skn, Lop]
+    };
+    /// Number of instructions in the original set; the rest are extensions
+    pub const NUM_BASE:usize = 12;
+
+    pub fn random<R: Rng + ?Sized>(rng: &mut R, set: &InstructionSet) -> Self {
+        let enabled = set.enabled_types();
+        enabled[rng.gen_range(0..enabled.len() as u32) as usize]
+    }
+
+    pub fn is_extension(self) -> bool {
+        usize::from(self.opcode()) >= Self::NUM_BASE
+    }
+
+    /// Whether src names a register. Otherwise it's a number below `SVM_REGISTER_BITS`: a shift,
+    /// a bit, a constant or a distance. dest is always a register.
+    pub fn src_is_register(self) -> bool {
+        use SvmInstructionTy::*;
+        matches!(self, Xor | Add | Sub | And | Oor | Mov | Pop | Lsb | Not | Cmv)
+    }
+
+    /// Number used for this instruction in the binary encoding. These are part of the file format,
+    /// so never renumber or reuse them.
+    pub fn opcode(self) -> u8 {
+        use SvmInstructionTy::*;
+        match self {
+            Xor => 0,
+            Add => 1,
+            Sub => 2,
+            And => 3,
+            Oor => 4,
+            Mov => 5,
+            Shl => 6,
+            Shr => 7,
+            Seb => 8,
+            Clb => 9,
+            Jis => 10,
+            Jns => 11,
+            Ldi => 12,
+            Pop => 13,
+            Lsb => 14,
+            Rol => 15,
+            Not => 16,
+            Cmv => 17,
+            Skn => 18,
+            Lop => 19,
+        }
+    }
+
+    pub fn from_opcode(opcode: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(opcode)).copied()
+    }
+}
+
+impl FromStr for SvmInstructionTy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use SvmInstructionTy::*;
+        match s.to_ascii_lowercase().as_str() {
+            "xor" => Ok(Xor),
+            "add" => Ok(Add),
+            "sub" => Ok(Sub),
+            "and" => Ok(And),
+            "oor" => Ok(Oor),
+            "mov" => Ok(Mov),
+            "shl" => Ok(Shl),
+            "shr" => Ok(Shr),
+            "seb" => Ok(Seb),
+            "clb" => Ok(Clb),
+            "jis" => Ok(Jis),
+            "jns" => Ok(Jns),
+            "ldi" => Ok(Ldi),
+            "pop" => Ok(Pop),
+            "lsb" => Ok(Lsb),
+            "rol" => Ok(Rol),
+            "not" => Ok(Not),
+            "cmv" => Ok(Cmv),
+            "skn" => Ok(Skn),
+            "lop" => Ok(Lop),
+            _ => Err(format!("Unknown instruction {:?}", s)),
         }
     }
 }
@@ -56,13 +142,92 @@ pub struct SvmInstruction {
 }
 
 impl SvmInstruction {
-    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+    pub fn random<R: Rng + ?Sized>(rng: &mut R, config: &SvmConfig) -> Self {
+        let ty = SvmInstructionTy::random(rng, &config.instruction_set);
         SvmInstruction {
-            ty: SvmInstructionTy::random(rng),
-            dest: rng.gen_range(0..32),
-            src: rng.gen_range(0..32),
+            ty,
+            dest: config.random_register(rng),
+            src: config.random_src(ty, rng),
+        }
+    }
+
+    /// Whether this is one of the original one-instruction skips, Jis or Jns
+    pub fn is_jump(self) -> bool {
+        matches!(self.ty, SvmInstructionTy::Jis | SvmInstructionTy::Jns)
+    }
+
+    /// Whether this changes which instruction runs next instead of writing a register
+    pub fn is_control(self) -> bool {
+        matches!(self.ty, SvmInstructionTy::Jis | SvmInstructionTy::Jns | SvmInstructionTy::Skn | SvmInstructionTy::Lop)
+    }
+
+    /// What this instruction sets its dest register to, given the values of the dest and src
+    /// registers beforehand. For the shifts, bit operations and Ldi src is a number rather than a
+    /// register, and the `src` value is ignored. Control instructions don't write anything and
+    /// return None.
+    pub fn result(self, dest: u32, src: u32) -> Option<u32> {
+        use SvmInstructionTy::*;
+        Some(match self.ty {
+            Xor => dest^src,
+            Add => dest.wrapping_add(src),
+            Sub => dest.wrapping_sub(src),
+            And => dest&src,
+            Oor => dest|src,
+            Mov => src,
+            Shl => dest << self.src,
+            Shr => dest >> self.src,
+            Seb => dest |  (1 << self.src), //set bit
+            Clb => dest & !(1 << self.src), //clear bit
+            Ldi => self.src.into(),
+            Pop => src.count_ones(),
+            Lsb => src & src.wrapping_neg(),
+            Rol => dest.rotate_left(self.src.into()),
+            Not => !src,
+            Cmv => if src != 0 { src } else { dest },
+            Jis | Jns | Skn | Lop => return None,
+        })
+    }
+
+    /// Whether a Jis or Jns skips the next instruction, given the value of its dest register.
+    pub fn skips(self, dest: u32) -> bool {
+        use SvmInstructionTy::*;
+        match self.ty {
+            Jis => dest & (1 << self.src) != 0, //jump forward one instruction if bit is set
+            Jns => dest & (1 << self.src) == 0, //jump if not set
+            _ => false,
+        }
+    }
+
+    /// Where a control instruction at `pc` sends execution, given the value of its dest register,
+    /// or None if execution carries on with the next instruction. The target can be past the end
+    /// of the program, which finishes it.
+    pub fn jump_target(self, pc: usize, dest: u32) -> Option<usize> {
+        use SvmInstructionTy::*;
+        let n = usize::from(self.src);
+        match self.ty {
+            Jis | Jns if self.skips(dest) => Some(pc + 2),
+            Skn if dest == 0 => Some(pc + 1 + n),
+            Lop if dest != 0 => Some(pc.saturating_sub(n + 1)),
+            _ => None,
         }
     }
+
+    pub fn encode(self) -> [u8; 3] {
+        [self.ty.opcode(), self.dest, self.src]
+    }
+
+    pub fn decode(bytes: [u8; 3]) -> Result<Self, String> {
+        let ty = SvmInstructionTy::from_opcode(bytes[0]).ok_or_else(|| format!("Unknown opcode {}", bytes[0]))?;
+        if !ty.src_is_register() && bytes[2] >= SVM_REGISTER_BITS {
+            return Err(format!("Operand {} out of range", bytes[2]));
+        }
+        Ok(SvmInstruction{ ty, dest: bytes[1], src: bytes[2] })
+    }
+
+    /// The highest register the instruction uses
+    pub fn max_register(self) -> u8 {
+        if self.ty.src_is_register() { self.dest.max(self.src) } else { self.dest }
+    }
 }
 
 impl fmt::Display for SvmInstruction {
@@ -71,53 +236,320 @@ impl fmt::Display for SvmInstruction {
     }
 }
 
+/// Which instructions random programs are made of, and the limits on how they run. Stored with
+/// checkpoints so a resumed run carries on with the same set.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct InstructionSet {
+    /// Bit per opcode
+    enabled: u32,
+    /// The enabled instructions in opcode order, up to `num_enabled`, so picking a random one
+    /// doesn't have to list them every time
+    types: [SvmInstructionTy; SvmInstructionTy::ALL.len()],
+    num_enabled: usize,
+    /// Steps a program can take before Lop stops jumping back. Every program still finishes after
+    /// that, since without jumping back it can only go forward.
+    pub step_budget: u32,
+}
+
+impl Default for InstructionSet {
+    /// The original 12 instructions
+    fn default() -> Self {
+        InstructionSet::new((1 << SvmInstructionTy::NUM_BASE) - 1, 1024)
+    }
+}
+
+impl InstructionSet {
+    fn new(enabled: u32, step_budget: u32) -> Self {
+        let mut set = InstructionSet{
+            enabled: 0,
+            types: [SvmInstructionTy::Xor; SvmInstructionTy::ALL.len()],
+            num_enabled: 0,
+            step_budget,
+        };
+        set.set_enabled(enabled);
+        set
+    }
+
+    fn set_enabled(&mut self, enabled: u32) {
+        self.enabled = enabled;
+        self.num_enabled = 0;
+        for ty in SvmInstructionTy::ALL {
+            if self.contains(ty) {
+                self.types[self.num_enabled] = ty;
+                self.num_enabled += 1;
+            }
+        }
+    }
+
+    pub fn all() -> Self {
+        InstructionSet::new((1 << SvmInstructionTy::ALL.len()) - 1, Self::default().step_budget)
+    }
+
+    /// The original set plus whatever extensions `instructions` use
+    pub fn covering(instructions: impl IntoIterator<Item=SvmInstruction>) -> Self {
+        let mut set = Self::default();
+        for ins in instructions {
+            set.enable(ins.ty);
+        }
+        set
+    }
+
+    pub fn contains(&self, ty: SvmInstructionTy) -> bool {
+        self.enabled & (1 << ty.opcode()) != 0
+    }
+
+    pub fn enable(&mut self, ty: SvmInstructionTy) {
+        self.set_enabled(self.enabled | 1 << ty.opcode());
+    }
+
+    /// The enabled instructions, in opcode order
+    pub fn enabled_types(&self) -> &[SvmInstructionTy] {
+        &self.types[..self.num_enabled]
+    }
+
+    /// Bit per opcode, as stored in files
+    pub fn bits(&self) -> u32 {
+        self.enabled
+    }
+
+    pub fn from_bits(bits: u32, step_budget: u32) -> Result<Self, String> {
+        let base = Self::default().enabled;
+        if bits & base != base || bits & !Self::all().enabled != 0 {
+            return Err(format!("Invalid instruction set {:#x}", bits));
+        }
+        Ok(InstructionSet::new(bits, step_budget))
+    }
+
+    /// Turns on the extensions in a comma separated list of their names, or all of them for
+    /// "all". The original instructions are always on.
+    pub fn enable_extensions(&mut self, list: &str) -> Result<(), String> {
+        for name in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            if name.eq_ignore_ascii_case("all") {
+                self.set_enabled(Self::all().enabled);
+                continue;
+            }
+            let ty:SvmInstructionTy = name.parse()?;
+            if !ty.is_extension() {
+                return Err(format!("{:?} isn't an extension, it's always on", ty));
+            }
+            self.enable(ty);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for InstructionSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let extensions:Vec<String> = SvmInstructionTy::ALL.iter()
+            .filter(|ty| ty.is_extension() && self.contains(**ty))
+            .map(|ty| format!("{:?}", ty))
+            .collect();
+        if extensions.is_empty() {
+            write!(f, "base")?;
+        } else {
+            write!(f, "base+{}", extensions.join(","))?;
+        }
+        write!(f, ", step budget {}", self.step_budget)
+    }
+}
+
+/// The shape of the machine programs run on, and the instructions they're made of. Shared by
+/// random generation, execution and fitness, and stored with checkpoints.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct SvmConfig {
+    /// Registers the machine has; instructions can only use these
+    pub num_registers: usize,
+    /// Register holding the guess when the program finishes
+    pub output: u8,
+    /// First of the registers the inputs are put in, one after another
+    pub first_input: u8,
+    pub num_inputs: usize,
+    pub instruction_set: InstructionSet,
+}
+
+impl SvmConfig {
+    /// The layout every program had before it was configurable: 32 registers, output in 0 and an
+    /// input per letter starting at 1
+    pub fn original(num_inputs: usize) -> Self {
+        SvmConfig{
+            num_registers: 32,
+            output: 0,
+            first_input: 1,
+            num_inputs,
+            instruction_set: InstructionSet::default(),
+        }
+    }
+
+    pub fn random_register<R: Rng + ?Sized>(&self, rng: &mut R) -> u8 {
+        rng.gen_range(0..=(self.num_registers - 1) as u8)
+    }
+
+    /// A register or a bit number, whichever `ty` takes
+    pub fn random_src<R: Rng + ?Sized>(&self, ty: SvmInstructionTy, rng: &mut R) -> u8 {
+        if ty.src_is_register() { self.random_register(rng) } else { rng.gen_range(0..SVM_REGISTER_BITS) }
+    }
+
+    pub fn inputs(&self) -> Range<usize> {
+        let first = usize::from(self.first_input);
+        first..first + self.num_inputs
+    }
+
+    /// Checks that the registers exist and the output isn't one of the inputs.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.num_registers == 0 || self.num_registers > SVM_MAX_REGISTERS {
+            return Err(format!("Number of registers must be 1 to {}", SVM_MAX_REGISTERS));
+        }
+        if usize::from(self.output) >= self.num_registers {
+            return Err(format!("Output register {} doesn't exist with {} registers", self.output, self.num_registers));
+        }
+        if self.inputs().end > self.num_registers {
+            return Err(format!("Input registers {:?} don't fit in {} registers", self.inputs(), self.num_registers));
+        }
+        if self.inputs().contains(&usize::from(self.output)) {
+            return Err(format!("Output register {} is also an input", self.output));
+        }
+        Ok(())
+    }
+
+    /// Checks that a program only uses registers this machine has.
+    pub fn check_program(&self, instructions: impl IntoIterator<Item=SvmInstruction>) -> Result<(), String> {
+        for ins in instructions {
+            if usize::from(ins.max_register()) >= self.num_registers {
+                return Err(format!("{} uses a register past the {} this machine has", ins, self.num_registers));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SvmConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{} registers, output {}, inputs {:?}, instruction set {}",
+            self.num_registers, self.output, self.inputs(), self.instruction_set,
+        )
+    }
+}
+
+/// Parses the `Display` form, `(Jis 25 16)`. The parentheses are optional and the name is case
+/// insensitive, so `jis 25 16` works too.
+impl FromStr for SvmInstruction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')).unwrap_or(s);
+        let parts:Vec<&str> = s.split_whitespace().collect();
+        if parts.len() != 3 {
+            return Err(format!("Expected an instruction and two operands, got {:?}", s));
+        }
+        let operand = |p:&str, max:usize| match p.parse::<u8>() {
+            Ok(n) if usize::from(n) < max => Ok(n),
+            _ => Err(format!("Operand {:?} must be a number below {}", p, max)),
+        };
+        let ty:SvmInstructionTy = parts[0].parse()?;
+        Ok(SvmInstruction {
+            ty,
+            dest: operand(parts[1], SVM_MAX_REGISTERS)?,
+            src: operand(parts[2], if ty.src_is_register() { SVM_MAX_REGISTERS } else { SVM_REGISTER_BITS.into() })?,
+        })
+    }
+}
+
+// The straightforward interpreter. Fitness runs `compile::CompiledProgram` instead, which is
+// tested against this. The trace subcommand uses it to show what a program does.
 #[derive(Debug)]
-pub struct SvmState<I: Iterator<Item=SvmInstruction> + fmt::Debug> {
-    instructions: I,
+pub struct SvmState<'a> {
+    instructions: &'a [SvmInstruction],
+    pc: usize,
+    steps: u32,
+    step_budget: u32,
+    max_steps: Option<u32>,
+    trace: Option<Vec<TraceStep>>,
     memory: SvmMemory,
 }
 
+/// One instruction run by `SvmState`, recorded if tracing is on.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct TraceStep {
+    pub pc: usize,
+    pub ins: SvmInstruction,
+    /// The dest register before and after; control instructions don't change it
+    pub before: u32,
+    pub after: u32,
+    /// Instruction that runs next, which is past the end once the program is done
+    pub next_pc: usize,
+}
+
 #[must_use]
 #[derive(Debug,Clone,Copy,PartialEq,Eq)]
 pub enum StepResult {
     Continue,
     Finish,
+    /// Hit the limit from `set_max_steps` before finishing
+    OutOfSteps,
 }
 
-impl<I: Iterator<Item=SvmInstruction> + fmt::Debug> SvmState<I> {
-    pub fn new(instructions: I) -> Self {
-        let memory = Box::new([0u32; SVM_NUM_REGISTERS]);
+impl<'a> SvmState<'a> {
+    pub fn new(instructions: &'a [SvmInstruction], config: &SvmConfig) -> Self {
+        let memory = vec![0u32; config.num_registers].into_boxed_slice();
         Self {
             instructions,
+            pc: 0,
+            steps: 0,
+            step_budget: config.instruction_set.step_budget,
+            max_steps: None,
+            trace: None,
             memory,
         }
     }
 
-    #[allow(dead_code)]
+    /// Stops running after this many steps, even though every program finishes eventually.
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.max_steps = Some(max_steps);
+    }
+
+    /// Records a `TraceStep` for every instruction run from now on.
+    pub fn record_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    pub fn trace(&self) -> &[TraceStep] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
     pub fn memory(&self) -> &[SvmRegister] {
-        self.memory.as_slice()
+        &self.memory
     }
 
     pub fn memory_mut(&mut self) -> &mut [SvmRegister] {
-        self.memory.as_mut_slice()
+        &mut self.memory
     }
 
     pub fn step(&mut self) -> StepResult {
-        if let Some(ins) = self.instructions.next() {
-            use SvmInstructionTy::*;
-            match ins.ty {
-                Xor => self.oper2(ins.dest, ins.src, |a,b| a^b),
-                Add => self.oper2(ins.dest, ins.src, u32::wrapping_add),
-                Sub => self.oper2(ins.dest, ins.src, u32::wrapping_sub),
-                And => self.oper2(ins.dest, ins.src, |a,b| a&b),
-                Oor => self.oper2(ins.dest, ins.src, |a,b| a|b),
-                Mov => self.oper2(ins.dest, ins.src, |_,b| b),
-                Shl => self.oper1(ins.dest, |a| a << ins.src),
-                Shr => self.oper1(ins.dest, |a| a >> ins.src),
-                Seb => self.oper1(ins.dest, |a| a |  (1 << ins.src)), //set bit
-                Clb => self.oper1(ins.dest, |a| a & !(1 << ins.src)), //clear bit
-                Jis => if self.get(ins.dest) & (1 << ins.src) != 0 {let _ = self.instructions.next();}, //jump forward one instruction if bit is set
-                Jns => if self.get(ins.dest) & (1 << ins.src) == 0 {let _ = self.instructions.next();}, //jump if not set
+        if let Some(&ins) = self.instructions.get(self.pc) {
+            if matches!(self.max_steps, Some(max) if self.steps >= max) {
+                return StepResult::OutOfSteps;
+            }
+            let dest = self.get(ins.dest);
+            let pc = self.pc;
+            self.pc += 1;
+            self.steps += 1;
+            // numeric operands aren't registers, and may be past the ones this machine has
+            let src = if ins.ty.src_is_register() { self.get(ins.src) } else { 0 };
+            match ins.result(dest, src) {
+                Some(res) => self.memory[ins.dest as usize] = res,
+                None => match ins.jump_target(pc, dest) {
+                    Some(target) if target > pc || self.steps < self.step_budget => self.pc = target,
+                    _ => (),
+                },
+            }
+            if let Some(trace) = &mut self.trace {
+                trace.push(TraceStep{ pc, ins, before: dest, after: self.memory[ins.dest as usize], next_pc: self.pc });
             }
             StepResult::Continue
         } else {
@@ -125,15 +557,82 @@ impl<I: Iterator<Item=SvmInstruction> + fmt::Debug> SvmState<I> {
         }
     }
 
-    fn oper2(&mut self, dest:u8, src:u8, f: impl FnOnce(u32, u32) -> u32) {
-        self.memory[dest as usize] = f(self.memory[dest as usize], self.memory[src as usize]);
+    fn get(&self, i:u8) -> u32 {
+        self.memory[i as usize]
     }
+}
 
-    fn oper1(&mut self, dest:u8, f: impl FnOnce(u32) -> u32) {
-        self.memory[dest as usize] = f(self.memory[dest as usize]);
+#[cfg(test)]
+mod svm_test {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn parse(text: &str) -> Vec<SvmInstruction> {
+        text.lines().map(|l| l.parse().unwrap()).collect()
     }
 
-    fn get(&self, i:u8) -> u32 {
-        self.memory[i as usize]
+    #[test]
+    fn max_steps() {
+        let program = parse("ldi 5 3\nldi 6 1\nsub 5 6\nlop 5 0\nseb 0 1");
+        let mut config = SvmConfig::original(1);
+        config.instruction_set = InstructionSet::all();
+        let mut state = SvmState::new(&program, &config);
+        state.set_max_steps(4);
+        for _ in 0..4 {
+            assert_eq!(state.step(), StepResult::Continue);
+        }
+        assert_eq!(state.step(), StepResult::OutOfSteps);
+        assert_eq!(state.memory()[5], 2);
+
+        let mut state = SvmState::new(&program, &config);
+        state.set_max_steps(9);
+        while state.step() == StepResult::Continue {}
+        assert_eq!(state.step(), StepResult::Finish);
+        assert_eq!((state.steps(), state.memory()[0]), (9, 2));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn trace() {
+        let program = parse("seb 0 3\njis 0 3\nseb 0 4\nxor 0 1");
+        let mut state = SvmState::new(&program, &SvmConfig::original(1));
+        state.memory_mut()[1] = 1;
+        state.record_trace();
+        while state.step() == StepResult::Continue {}
+        let steps:Vec<_> = state.trace().iter().map(|t| (t.pc, t.before, t.after, t.next_pc)).collect();
+        assert_eq!(steps, vec![(0, 0, 8, 1), (1, 8, 8, 3), (3, 8, 9, 4)]);
+        assert_eq!(state.trace()[2].ins, program[3]);
+    }
+
+    #[test]
+    fn config() {
+        let mut config = SvmConfig::original(10);
+        assert_eq!(config.inputs(), 1..11);
+        assert!(config.validate().is_ok());
+        config.num_registers = 10;
+        assert!(config.validate().is_err());
+        config.num_registers = 12;
+        config.output = 5;
+        assert!(config.validate().is_err());
+        config.output = 11;
+        assert!(config.validate().is_ok());
+
+        assert!(config.check_program(parse("xor 11 10\nshl 0 31")).is_ok());
+        assert!(config.check_program(parse("xor 11 12")).is_err());
+
+        config.instruction_set = InstructionSet::all();
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(config.check_program((0..1000).map(|_| SvmInstruction::random(&mut rng, &config))).is_ok());
+    }
+
+    #[test]
+    fn enabled_types() {
+        let mut set = InstructionSet::default();
+        assert_eq!(set.enabled_types(), &SvmInstructionTy::ALL[..SvmInstructionTy::NUM_BASE]);
+        set.enable_extensions("lop,pop").unwrap();
+        let expected:Vec<_> = SvmInstructionTy::ALL.iter().copied().filter(|ty| set.contains(*ty)).collect();
+        assert_eq!(set.enabled_types(), expected.as_slice());
+        assert_eq!(set, InstructionSet::from_bits(set.bits(), set.step_budget).unwrap());
+        assert_eq!(InstructionSet::all().enabled_types(), &SvmInstructionTy::ALL);
+    }
+}